@@ -0,0 +1,7 @@
+#![no_main]
+use adobe_license_decoder::OperatingConfig;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = OperatingConfig::from_ccp_data(data);
+});
@@ -0,0 +1,16 @@
+#![no_main]
+use adobe_license_decoder::{FileInfo, OperatingConfig};
+use libfuzzer_sys::fuzz_target;
+
+// The first line of the input is the license file's name (without
+// extension), and the rest is the file's content.
+fuzz_target!(|data: &[u8]| {
+    let mut parts = data.splitn(2, |&b| b == b'\n');
+    let name = std::str::from_utf8(parts.next().unwrap_or_default());
+    let content = std::str::from_utf8(parts.next().unwrap_or_default());
+    if let (Ok(name), Ok(content)) = (name, content) {
+        if let Ok(info) = FileInfo::from_name_and_extension(name, "operatingconfig") {
+            let _ = OperatingConfig::from_license_data(&info, content);
+        }
+    }
+});
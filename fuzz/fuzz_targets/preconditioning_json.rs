@@ -0,0 +1,10 @@
+#![no_main]
+use adobe_license_decoder::utilities::JsonMap;
+use adobe_license_decoder::OperatingConfig;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(data) = serde_json::from_slice::<JsonMap>(data) {
+        let _ = OperatingConfig::from_preconditioning_json(&data);
+    }
+});
@@ -0,0 +1,332 @@
+/*
+Copyright 2020 Adobe
+All Rights Reserved.
+
+NOTICE: Adobe permits you to use, modify, and distribute this file in
+accordance with the terms of the Adobe license agreement accompanying
+it.
+*/
+//! Analysis of the licenses installed together on a machine.
+//!
+//! When several licenses are installed for an application, it uses the
+//! one with the highest precedence, and of those the one installed most
+//! recently.  This module works out which license each application uses,
+//! which licenses it shadows, and which combinations of licenses are
+//! likely to behave in ways the administrator didn't intend.
+use crate::types::{DeploymentMode, OperatingConfig, Precedence};
+use serde::Serialize;
+use std::cmp::Ordering;
+
+/// The license that an application will use when several are installed,
+/// together with the installed licenses for that application that it shadows.
+pub struct EffectiveLicense<'a> {
+    pub app_id: String,
+    pub effective: &'a OperatingConfig,
+    pub shadowed: Vec<Shadowed<'a>>,
+}
+
+/// A license that is not used because another license takes priority.
+pub struct Shadowed<'a> {
+    pub license: &'a OperatingConfig,
+    pub reason: ShadowReason,
+}
+
+/// Why a license is shadowed by the effective license.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ShadowReason {
+    /// The effective license has a higher precedence.
+    LowerPrecedence,
+    /// The effective license has the same precedence and was installed later.
+    InstalledEarlier,
+    /// The effective license has the same precedence and install time,
+    /// so which license gets used cannot be determined.
+    Undetermined,
+}
+
+impl std::fmt::Display for ShadowReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ShadowReason::LowerPrecedence => "lower precedence".fmt(f),
+            ShadowReason::InstalledEarlier => "same precedence, installed earlier".fmt(f),
+            ShadowReason::Undetermined => "same precedence and install time".fmt(f),
+        }
+    }
+}
+
+/// Compare two licenses for the same app by priority: the license with
+/// the higher precedence wins and, among licenses with the same precedence,
+/// the most recently installed one wins.
+pub fn compare_priority(oc1: &OperatingConfig, oc2: &OperatingConfig) -> Ordering {
+    match oc1.precedence.cmp(&oc2.precedence) {
+        Ordering::Equal => oc1.install_time.cmp(&oc2.install_time),
+        otherwise => otherwise,
+    }
+}
+
+/// Determine, for each application, which of the given licenses will be used
+/// and which are shadowed.  The result is sorted by app ID.
+pub fn effective_licenses(ocs: &[OperatingConfig]) -> Vec<EffectiveLicense<'_>> {
+    let mut app_ids: Vec<&str> = ocs.iter().map(|oc| oc.app_id.as_str()).collect();
+    app_ids.sort_unstable();
+    app_ids.dedup();
+    let mut result = Vec::new();
+    for app_id in app_ids {
+        let mut candidates: Vec<&OperatingConfig> =
+            ocs.iter().filter(|oc| oc.app_id == app_id).collect();
+        // stable sort, so equal-priority licenses stay in their given order
+        candidates.sort_by(|oc1, oc2| compare_priority(oc2, oc1));
+        let effective = candidates[0];
+        let shadowed = candidates[1..]
+            .iter()
+            .map(|license| {
+                let reason = if license.precedence < effective.precedence {
+                    ShadowReason::LowerPrecedence
+                } else if license.install_time < effective.install_time {
+                    ShadowReason::InstalledEarlier
+                } else {
+                    ShadowReason::Undetermined
+                };
+                Shadowed { license, reason }
+            })
+            .collect();
+        result.push(EffectiveLicense {
+            app_id: app_id.to_string(),
+            effective,
+            shadowed,
+        })
+    }
+    result
+}
+
+/// A problem caused by several licenses being installed for the same app.
+pub struct Conflict<'a> {
+    pub kind: ConflictKind,
+    pub app_id: String,
+    /// The licenses involved, highest priority first.
+    pub licenses: Vec<&'a OperatingConfig>,
+    /// What the conflict means for the app.
+    pub consequence: String,
+}
+
+/// The kinds of conflict between installed licenses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConflictKind {
+    /// Licenses from different packages have the same precedence,
+    /// so the one used depends on the order they were installed.
+    SamePrecedence,
+    /// An FRL LAN license and an FRL Online license compete for the app.
+    LanAndOnline,
+    /// An Acrobat Standard license is shadowed by an Acrobat Pro license.
+    StandardShadowedByPro,
+}
+
+impl std::fmt::Display for ConflictKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConflictKind::SamePrecedence => {
+                "licenses from different packages have the same precedence".fmt(f)
+            }
+            ConflictKind::LanAndOnline => {
+                "FRL LAN and FRL Online licenses compete".fmt(f)
+            }
+            ConflictKind::StandardShadowedByPro => {
+                "Acrobat Standard license is shadowed by Acrobat Pro".fmt(f)
+            }
+        }
+    }
+}
+
+/// Find the conflicts among the given licenses.  The result is
+/// sorted by app ID.
+pub fn find_conflicts(ocs: &[OperatingConfig]) -> Vec<Conflict<'_>> {
+    let mut result = Vec::new();
+    for el in effective_licenses(ocs) {
+        let effective = el.effective;
+        let mut licenses = vec![effective];
+        licenses.extend(el.shadowed.iter().map(|sh| sh.license));
+        // licenses with the same precedence from different packages
+        let mut precedences: Vec<Precedence> =
+            licenses.iter().map(|oc| oc.precedence).collect();
+        precedences.sort_unstable_by(|p1, p2| p2.cmp(p1));
+        precedences.dedup();
+        for precedence in precedences {
+            let same: Vec<&OperatingConfig> = licenses
+                .iter()
+                .filter(|oc| oc.precedence == precedence)
+                .copied()
+                .collect();
+            if same.len() > 1 && same.iter().any(|oc| oc.npd_id != same[0].npd_id) {
+                let consequence = if precedence != effective.precedence {
+                    format!(
+                        "None of these licenses is used, because a license with higher \
+                        precedence (npdId {}) is installed.",
+                        effective.npd_id
+                    )
+                } else if same[0].install_time == same[1].install_time {
+                    "These licenses were installed at the same time, so which of them \
+                    is used cannot be determined."
+                        .to_string()
+                } else {
+                    format!(
+                        "The most recently installed of these licenses (npdId {}) is used. \
+                        Installing any of the others again will change which license is used.",
+                        same[0].npd_id
+                    )
+                };
+                result.push(Conflict {
+                    kind: ConflictKind::SamePrecedence,
+                    app_id: el.app_id.clone(),
+                    licenses: same,
+                    consequence,
+                })
+            }
+        }
+        // LAN and online licenses for the same app
+        let lan_or_online: Vec<&OperatingConfig> = licenses
+            .iter()
+            .filter(|oc| {
+                matches!(
+                    oc.mode,
+                    DeploymentMode::FrlLan(..) | DeploymentMode::FrlOnline(_)
+                )
+            })
+            .copied()
+            .collect();
+        if lan_or_online
+            .iter()
+            .any(|oc| matches!(oc.mode, DeploymentMode::FrlLan(..)))
+            && lan_or_online
+                .iter()
+                .any(|oc| matches!(oc.mode, DeploymentMode::FrlOnline(_)))
+        {
+            let uses_either = lan_or_online.iter().any(|oc| std::ptr::eq(*oc, effective));
+            let consequence = if uses_either {
+                format!(
+                    "The app uses the {} license (npdId {}), so it never contacts the \
+                    server named in the other licenses.",
+                    effective.mode, effective.npd_id
+                )
+            } else {
+                format!(
+                    "The app uses the {} license (npdId {}), so it contacts none of \
+                    the servers named in these licenses.",
+                    effective.mode, effective.npd_id
+                )
+            };
+            result.push(Conflict {
+                kind: ConflictKind::LanAndOnline,
+                app_id: el.app_id.clone(),
+                licenses: lan_or_online,
+                consequence,
+            })
+        }
+        // Acrobat Standard licenses shadowed by Acrobat Pro licenses
+        let pro = licenses
+            .iter()
+            .find(|oc| oc.precedence == Precedence::AcrobatPro);
+        let standard: Vec<&OperatingConfig> = licenses
+            .iter()
+            .filter(|oc| oc.precedence == Precedence::AcrobatStandard)
+            .copied()
+            .collect();
+        if let (Some(pro), false) = (pro, standard.is_empty()) {
+            let mut involved = vec![*pro];
+            involved.extend(standard);
+            let consequence = format!(
+                "Acrobat runs as Acrobat Pro (npdId {}), so the Acrobat Standard \
+                license is never used.",
+                pro.npd_id
+            );
+            result.push(Conflict {
+                kind: ConflictKind::StandardShadowedByPro,
+                app_id: el.app_id.clone(),
+                licenses: involved,
+                consequence,
+            })
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::FileInfo;
+    use chrono::{Duration, Local};
+
+    #[test]
+    fn test_effective_licenses() {
+        let info = FileInfo::from_path("rsrc/files").unwrap();
+        let mut ocs = OperatingConfig::from_directory(&info).unwrap().licenses;
+        // make the isolated Bridge license (index 2) the most recent one
+        let now = Local::now();
+        for (i, oc) in ocs.iter_mut().enumerate() {
+            oc.install_time = Some(now - Duration::minutes(10 - i as i64));
+        }
+        let effective = effective_licenses(&ocs);
+        assert_eq!(effective.len(), 3);
+        assert_eq!(effective[0].app_id, "Bridge1");
+        assert!(effective[0].effective.npd_id.starts_with("ZGQz"));
+        assert_eq!(effective[0].shadowed.len(), 1);
+        assert!(effective[0].shadowed[0].license.npd_id.starts_with("OTUz"));
+        assert_eq!(
+            effective[0].shadowed[0].reason,
+            ShadowReason::InstalledEarlier
+        );
+        assert_eq!(effective[1].app_id, "Illustrator1");
+        assert!(effective[1].shadowed.is_empty());
+        assert_eq!(effective[2].app_id, "Photoshop1");
+    }
+
+    #[test]
+    fn test_find_conflicts() {
+        let info = FileInfo::from_path("rsrc/files").unwrap();
+        let mut ocs = OperatingConfig::from_directory(&info).unwrap().licenses;
+        let conflicts = find_conflicts(&ocs);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].kind, ConflictKind::SamePrecedence);
+        assert_eq!(conflicts[0].app_id, "Bridge1");
+        assert_eq!(conflicts[0].licenses.len(), 2);
+        // make one Bridge license Acrobat Pro online, the other Acrobat Standard LAN
+        ocs[2].precedence = Precedence::AcrobatPro;
+        ocs[2].mode = DeploymentMode::FrlOnline("https://lcs-cops.adobe.io".into());
+        ocs[0].precedence = Precedence::AcrobatStandard;
+        ocs[0].mode = DeploymentMode::FrlLan("https://lan:8443".into(), None);
+        let conflicts = find_conflicts(&ocs);
+        let kinds: Vec<ConflictKind> = conflicts.iter().map(|c| c.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ConflictKind::LanAndOnline,
+                ConflictKind::StandardShadowedByPro
+            ]
+        );
+        assert!(conflicts[1].licenses[0].npd_id.starts_with("ZGQz"));
+        assert!(conflicts[0]
+            .consequence
+            .starts_with("The app uses the FRL Online"));
+        // LAN and online licenses shadowed by a higher-precedence isolated license
+        let path = "rsrc/files/QnJpZGdlMXt9MjAxODA3MjAwNA-OTUzZTViZWYtYWJmMy00NGUxLWFjYjUtZmZhN2MyMDY4YjQx-80.operatingconfig";
+        let mut online =
+            OperatingConfig::from_license_file(&FileInfo::from_path(path).unwrap())
+                .unwrap();
+        online.npd_id = "online".into();
+        online.mode = DeploymentMode::FrlOnline("https://lcs-cops.adobe.io".into());
+        ocs[0].precedence = Precedence::CcSingleApp;
+        ocs[2].precedence = Precedence::CcAllApps;
+        ocs[2].mode = DeploymentMode::FrlIsolated(vec!["code".into()]);
+        ocs.push(online);
+        let conflicts = find_conflicts(&ocs);
+        let lan_and_online = conflicts
+            .iter()
+            .find(|c| c.kind == ConflictKind::LanAndOnline);
+        let consequence = &lan_and_online.unwrap().consequence;
+        assert!(consequence
+            .starts_with("The app uses the FRL Isolated (1 census code) license"));
+        assert!(consequence.contains(&ocs[2].npd_id));
+        assert!(consequence
+            .ends_with("contacts none of the servers named in these licenses."));
+    }
+}
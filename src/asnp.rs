@@ -0,0 +1,217 @@
+/*
+Copyright 2020 Adobe
+All Rights Reserved.
+
+NOTICE: Adobe permits you to use, modify, and distribute this file in
+accordance with the terms of the Adobe license agreement accompanying
+it.
+*/
+//! The ASNP files shipped in preconditioning data.
+//!
+//! Each license in a package names (by its `templateId`) the ASNP file
+//! that holds the template for its license profile.  The template is
+//! filled in with the values that Adobe signed in the license, so the
+//! license details in an ASNP can only be seen alongside its license.
+use crate::models::{AsnpEnvelope, AsnpPayload, PackageFile, SpecVersion};
+use crate::types::{FileError, OperatingConfig};
+use crate::utilities::{date_from_epoch_millis, in_layer, json_field, JsonMap};
+use eyre::{Result, WrapErr};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// The decoded content of a single ASNP file.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Asnp {
+    pub filename: String,
+    pub template_id: String,
+    pub spec_version: String,
+    pub app_id: String,
+    /// The license ID, which is only known for licenses that
+    /// don't get their profile from a server.
+    pub license_id: Option<String>,
+    pub license_type: Option<i64>,
+    pub activation_type: Option<String>,
+    pub effective_end_date: String,
+    /// The content of the ASNP file.
+    #[serde(skip)]
+    pub envelope: AsnpEnvelope,
+    /// The payload of the ASNP file, filled in with the values from its license.
+    #[serde(skip)]
+    pub payload: AsnpPayload,
+}
+
+impl Asnp {
+    /// Decode an ASNP file from preconditioning data, filling in its
+    /// template with the values from the license that uses it (if any).
+    pub fn from_package_file(
+        file: &PackageFile, ocs: &[OperatingConfig],
+    ) -> Result<Asnp> {
+        let values = ocs
+            .iter()
+            .find(|oc| template_id(oc) == Some(file.name.as_str()))
+            .map_or_else(HashMap::new, template_values);
+        let json = in_layer("content", file.decode_text())?;
+        let spec = AsnpEnvelope::declared_spec_version(&json);
+        SpecVersion::decode_declared(spec.as_ref(), || {
+            let envelope = AsnpEnvelope::from_json(&json, spec.as_ref());
+            let envelope = in_layer("content", envelope)?;
+            Asnp::from_envelope(file, envelope, &values)
+        })
+    }
+
+    fn from_envelope(
+        file: &PackageFile, envelope: AsnpEnvelope, values: &HashMap<String, String>,
+    ) -> Result<Asnp> {
+        let payload = envelope.decode_payload(values);
+        let payload = in_layer("content", in_layer("payload", payload))?;
+        let legacy = payload.decode_legacy_profile();
+        let legacy = in_layer("content", in_layer("payload", legacy))?;
+        let relationships = payload.decode_relationship_profile();
+        let relationships = in_layer("content", in_layer("payload", relationships))?;
+        let effective_end_date = match legacy.effective_end_timestamp {
+            Some(timestamp) => date_from_epoch_millis(&timestamp.to_string())?,
+            None => "controlled by server".to_string(),
+        };
+        Ok(Asnp {
+            filename: format!("{}.{}", file.name, file.extension),
+            template_id: file.name.clone(),
+            spec_version: envelope.asnp_spec_version.clone(),
+            app_id: payload.control_profile.ngl_app_id.clone(),
+            license_id: legacy.license_id,
+            license_type: legacy.license_type,
+            activation_type: relationships
+                .into_iter()
+                .find_map(|relationship| relationship.activation_type),
+            effective_end_date,
+            envelope,
+            payload,
+        })
+    }
+
+    /// A warning that this file was decoded with best effort,
+    /// if the decoder doesn't know its spec version.
+    pub fn spec_version_warning(&self) -> Option<String> {
+        self.envelope.spec_version().warning()
+    }
+}
+
+/// The ASNP files decoded from preconditioning data.
+#[derive(Default)]
+pub struct PackageAsnps {
+    /// The decoded ASNP files, sorted by app ID.
+    pub asnps: Vec<Asnp>,
+    /// The ASNP files that could not be decoded.
+    pub errors: Vec<FileError>,
+}
+
+impl PackageAsnps {
+    /// Decode the ASNP files in parsed preconditioning data, whose licenses
+    /// have already been decoded.  Files that can't be decoded don't stop
+    /// the others from being decoded; they are returned along with their
+    /// errors.
+    pub fn from_preconditioning_json(
+        data: &JsonMap, ocs: &[OperatingConfig],
+    ) -> Result<PackageAsnps> {
+        let asnps = json_field(data, "asnps").clone();
+        let asnps: Option<Vec<PackageFile>> = serde_json::from_value(asnps)
+            .wrap_err("Invalid preconditioning data: bad ASNP data")?;
+        let mut result = PackageAsnps::default();
+        for file in asnps.unwrap_or_default() {
+            match Asnp::from_package_file(&file, ocs) {
+                Ok(asnp) => result.asnps.push(asnp),
+                Err(error) => result.errors.push(FileError {
+                    pathname: format!("{}.{}", file.name, file.extension),
+                    error,
+                }),
+            }
+        }
+        result.asnps.sort_by(|a1, a2| a1.app_id.cmp(&a2.app_id));
+        Ok(result)
+    }
+
+    /// The licenses that have no matching ASNP file: that is, no file
+    /// with the license's template ID whose app ID is the license's.
+    /// Licenses that don't use an ASNP (such as SDL licenses) are skipped.
+    pub fn licenses_without_asnp<'a>(
+        &self, ocs: &'a [OperatingConfig],
+    ) -> Vec<&'a OperatingConfig> {
+        ocs.iter()
+            .filter(|oc| match template_id(oc) {
+                Some(id) => !self
+                    .asnps
+                    .iter()
+                    .any(|asnp| asnp.template_id == id && asnp.app_id == oc.app_id),
+                None => false,
+            })
+            .collect()
+    }
+}
+
+fn template_id(oc: &OperatingConfig) -> Option<&str> {
+    let asnp = oc.payload.asnp_data.as_ref()?;
+    Some(asnp.template_id.as_str())
+}
+
+/// The values used to fill in the ASNP template of a license.
+fn template_values(oc: &OperatingConfig) -> HashMap<String, String> {
+    let values = oc.payload.adobe_signed_values().map(|adobe| &adobe.values);
+    match values.map(serde_json::to_value) {
+        Some(Ok(serde_json::Value::Object(map))) => map
+            .into_iter()
+            .filter_map(|(name, value)| match value.as_str()? {
+                // missing values are filled in as null
+                "" => None,
+                value => Some((name, value.to_string())),
+            })
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::{json_from_file, FileInfo};
+
+    fn decode_package(name: &str) -> (Vec<OperatingConfig>, PackageAsnps) {
+        let path = format!("rsrc/packages/mac/{}/ngl-preconditioning-data.json", name);
+        let info = FileInfo::from_path(&path).unwrap();
+        let data = json_from_file(&info).unwrap();
+        let ocs = OperatingConfig::from_preconditioning_json(&data).unwrap();
+        let asnps = PackageAsnps::from_preconditioning_json(&data, &ocs).unwrap();
+        (ocs, asnps)
+    }
+
+    #[test]
+    fn test_isolated_asnps() {
+        let (mut ocs, package) = decode_package("isolated-photoshop");
+        assert!(package.errors.is_empty());
+        assert_eq!(package.asnps.len(), 2);
+        let asnp = &package.asnps[1];
+        assert_eq!(asnp.app_id, "Photoshop1");
+        assert_eq!(asnp.spec_version, "1.2");
+        assert_eq!(asnp.license_id.as_deref(), Some("96CB7B129BCA2124D48A"));
+        assert_eq!(asnp.license_type, Some(5));
+        assert_eq!(asnp.activation_type.as_deref(), Some("FRL_ISOLATED"));
+        assert!(package.licenses_without_asnp(&ocs).is_empty());
+        // a license whose template isn't shipped
+        ocs[0].payload.asnp_data.as_mut().unwrap().template_id = "missing".into();
+        let unmatched = package.licenses_without_asnp(&ocs);
+        assert_eq!(unmatched.len(), 1);
+        assert_eq!(unmatched[0].app_id, ocs[0].app_id);
+    }
+
+    #[test]
+    fn test_online_asnps() {
+        let (ocs, package) = decode_package("online-proxy-premiere");
+        assert!(package.errors.is_empty());
+        assert_eq!(package.asnps.len(), ocs.len());
+        for asnp in &package.asnps {
+            assert_eq!(asnp.license_id, None);
+            assert_eq!(asnp.effective_end_date, "controlled by server");
+            assert_eq!(asnp.activation_type.as_deref(), Some("FRL_CONNECTED"));
+        }
+        assert!(package.licenses_without_asnp(&ocs).is_empty());
+    }
+}
@@ -0,0 +1,395 @@
+/*
+Copyright 2020 Adobe
+All Rights Reserved.
+
+NOTICE: Adobe permits you to use, modify, and distribute this file in
+accordance with the terms of the Adobe license agreement accompanying
+it.
+*/
+//! The X.509 certificates that are shipped with licenses.
+//!
+//! Preconditioning data includes the DER-encoded certificates needed to
+//! check the signatures in its licenses, and the licensing toolkit installs
+//! them alongside the license files.
+use crate::models::PackageFile;
+use crate::types::{FileError, OperatingConfig};
+use crate::utilities::{json_field, JsonMap};
+use chrono::{DateTime, Local, TimeZone};
+use eyre::{eyre, Result, WrapErr};
+use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY, SHA256};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use x509_parser::certificate::X509Certificate;
+use x509_parser::time::ASN1Time;
+use x509_parser::x509::X509Name;
+
+/// A set of DER-encoded certificates, indexed by file name, along with
+/// the errors found loading any certificates that couldn't be added.
+#[derive(Debug, Clone, Default)]
+pub struct CertificateStore {
+    certificates: HashMap<String, Vec<u8>>,
+    errors: BTreeMap<String, String>,
+}
+
+impl CertificateStore {
+    /// Add a certificate, replacing any with the same file name.
+    pub fn add(&mut self, filename: &str, der: Vec<u8>) {
+        self.certificates.insert(filename.to_string(), der);
+    }
+
+    /// The certificate with the given file name, if there is one.
+    pub fn get(&self, filename: &str) -> Option<&[u8]> {
+        self.certificates.get(filename).map(|der| der.as_slice())
+    }
+
+    pub fn len(&self) -> usize {
+        self.certificates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.certificates.is_empty()
+    }
+
+    /// The certificates that couldn't be loaded into the store,
+    /// sorted by file name, with the errors found loading them.
+    pub fn errors(&self) -> Vec<FileError> {
+        let error = |(pathname, message): (&String, &String)| FileError {
+            pathname: pathname.clone(),
+            error: eyre!("{}", message),
+        };
+        self.errors.iter().map(error).collect()
+    }
+
+    fn add_error(&mut self, filename: &str, error: eyre::Report) {
+        self.errors
+            .insert(filename.to_string(), format!("{:#}", error));
+    }
+
+    /// Parse every certificate in the store.  The result is sorted by
+    /// subject, and certificates that can't be parsed (or couldn't be
+    /// loaded) are returned with their errors.
+    pub fn inspect(&self) -> CertificateReport {
+        let mut result = CertificateReport {
+            errors: self.errors(),
+            ..Default::default()
+        };
+        for (filename, der) in &self.certificates {
+            match CertificateInfo::from_der(filename, der) {
+                Ok(info) => result.certificates.push(info),
+                Err(error) => result.errors.push(FileError {
+                    pathname: filename.clone(),
+                    error,
+                }),
+            }
+        }
+        result.certificates.sort_by(|c1, c2| {
+            (&c1.subject, &c1.serial_number).cmp(&(&c2.subject, &c2.serial_number))
+        });
+        result
+            .errors
+            .sort_by(|e1, e2| e1.pathname.cmp(&e2.pathname));
+        result
+    }
+
+    /// The certificates shipped in parsed preconditioning data.  Any
+    /// certificate whose content can't be decoded is recorded as an error,
+    /// and doesn't stop the others from being loaded.
+    pub fn from_preconditioning_json(data: &JsonMap) -> Result<CertificateStore> {
+        let certs = json_field(data, "certificates").clone();
+        let certs: Option<Vec<PackageFile>> = serde_json::from_value(certs)
+            .wrap_err("Invalid preconditioning data: bad certificate data")?;
+        let mut result = CertificateStore::default();
+        for cert in certs.unwrap_or_default() {
+            let filename = format!("{}.{}", cert.name, cert.extension);
+            match cert.decode_content() {
+                Ok(der) => result.add(&filename, der),
+                Err(err) => {
+                    result.add_error(&filename, err.wrap_err("Invalid certificate data"))
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// The certificates (`.der` files) in a directory.  Any certificate
+    /// that can't be read is recorded as an error, and doesn't stop the
+    /// others from being loaded.
+    pub fn from_directory(path: &str) -> Result<CertificateStore> {
+        let pattern = format!("{}/*.der", glob::Pattern::escape(path));
+        let mut result = CertificateStore::default();
+        for path in glob::glob(&pattern)? {
+            let path = path?;
+            let filename = match path.file_name() {
+                Some(filename) => filename.to_string_lossy().to_string(),
+                None => continue,
+            };
+            match std::fs::read(&path) {
+                Ok(der) => result.add(&filename, der),
+                Err(err) => {
+                    let err = eyre!(err).wrap_err("Can't read certificate");
+                    result.add_error(&filename, err)
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// The certificates in the directory containing a file.
+    pub fn from_file_directory(pathname: &str) -> Result<CertificateStore> {
+        match Path::new(pathname).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => {
+                CertificateStore::from_directory(&dir.to_string_lossy())
+            }
+            _ => CertificateStore::from_directory("."),
+        }
+    }
+
+    /// Whether this store holds the given certificate, or a certificate
+    /// that issued it.
+    pub(crate) fn anchors(&self, cert: &X509Certificate) -> bool {
+        self.certificates.values().any(|der| {
+            match x509_parser::parse_x509_certificate(der) {
+                Ok((_, root)) => {
+                    root.as_ref() == cert.as_ref()
+                        || (root.subject() == cert.issuer()
+                            && cert.verify_signature(Some(root.public_key())).is_ok())
+                }
+                Err(_) => false,
+            }
+        })
+    }
+}
+
+/// The certificates parsed from a certificate store.
+#[derive(Default)]
+pub struct CertificateReport {
+    /// The parsed certificates, sorted by subject.
+    pub certificates: Vec<CertificateInfo>,
+    /// The certificates that could not be parsed.
+    pub errors: Vec<FileError>,
+}
+
+/// The details of a certificate.  Serial numbers and fingerprints are in
+/// hex, as they are in signature headers.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateInfo {
+    pub filename: String,
+    pub subject: String,
+    pub issuer: String,
+    pub serial_number: String,
+    pub valid_from: String,
+    pub valid_until: String,
+    #[serde(skip)]
+    pub expiry_time: DateTime<Local>,
+    pub sha1_fingerprint: String,
+    pub sha256_fingerprint: String,
+}
+
+impl CertificateInfo {
+    /// Parse a DER-encoded certificate.
+    pub fn from_der(filename: &str, der: &[u8]) -> Result<CertificateInfo> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der)
+            .map_err(|e| eyre!(e))
+            .wrap_err("Invalid certificate data")?;
+        let validity = cert.validity();
+        let valid_from = datetime_from_asn1(&validity.not_before)?;
+        let expiry_time = datetime_from_asn1(&validity.not_after)?;
+        Ok(CertificateInfo {
+            filename: filename.to_string(),
+            subject: cert.subject().to_string(),
+            issuer: cert.issuer().to_string(),
+            // the serial is shown with all its bytes, as it is encoded
+            serial_number: hex(cert.raw_serial()).to_lowercase(),
+            valid_from: valid_from.format("%Y-%m-%d").to_string(),
+            valid_until: expiry_time.format("%Y-%m-%d").to_string(),
+            expiry_time,
+            sha1_fingerprint: hex(digest(&SHA1_FOR_LEGACY_USE_ONLY, der).as_ref()),
+            sha256_fingerprint: hex(digest(&SHA256, der).as_ref()),
+        })
+    }
+
+    /// Whether, as of `now`, the certificate has expired or will expire
+    /// before the given license expiry.
+    pub fn problem(
+        &self, now: DateTime<Local>, license_expiry: Option<DateTime<Local>>,
+    ) -> Option<CertificateProblem> {
+        if self.expiry_time < now {
+            Some(CertificateProblem::Expired)
+        } else if matches!(license_expiry, Some(expiry) if self.expiry_time < expiry) {
+            Some(CertificateProblem::ExpiresBeforeLicense)
+        } else {
+            None
+        }
+    }
+}
+
+/// The latest expiry of the licenses that name each certificate in their
+/// signatures, indexed by the certificate's SHA-1 fingerprint (in upper
+/// case hex).  Licenses whose expiry is controlled by a server, and those
+/// whose signature headers can't be decoded, don't count.
+pub fn signed_license_expiries(
+    ocs: &[OperatingConfig],
+) -> HashMap<String, DateTime<Local>> {
+    let mut result: HashMap<String, DateTime<Local>> = HashMap::new();
+    for oc in ocs {
+        let expiry = match oc.expiry_time {
+            Some(expiry) => expiry,
+            None => continue,
+        };
+        let mut headers = oc.envelope.decode_signature_headers().unwrap_or_default();
+        headers.extend(
+            oc.payload
+                .decode_customer_cert_headers()
+                .unwrap_or_default(),
+        );
+        for details in headers.iter().flat_map(|h| &h.certificate_details) {
+            let latest = result
+                .entry(details.sha1_hash.to_uppercase())
+                .or_insert(expiry);
+            *latest = (*latest).max(expiry);
+        }
+    }
+    result
+}
+
+/// A problem with the validity window of a certificate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CertificateProblem {
+    Expired,
+    ExpiresBeforeLicense,
+}
+
+impl std::fmt::Display for CertificateProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CertificateProblem::Expired => "expired".fmt(f),
+            CertificateProblem::ExpiresBeforeLicense => {
+                "expires before the license does".fmt(f)
+            }
+        }
+    }
+}
+
+fn datetime_from_asn1(time: &ASN1Time) -> Result<DateTime<Local>> {
+    Local
+        .timestamp_opt(time.timestamp(), 0)
+        .single()
+        .ok_or_else(|| eyre!("Certificate date out of range: {}", time))
+}
+
+pub(crate) fn common_name(name: &X509Name) -> String {
+    name.iter_common_name()
+        .next()
+        .and_then(|cn| cn.as_str().ok())
+        .map_or_else(|| name.to_string(), |cn| cn.to_string())
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::{json_from_file, FileInfo};
+
+    #[test]
+    fn test_from_directory() {
+        let certificates =
+            CertificateStore::from_directory("rsrc/verify/licenses").unwrap();
+        assert_eq!(certificates.len(), 2);
+        let report = certificates.inspect();
+        assert!(report.errors.is_empty());
+        assert_eq!(
+            report.certificates[0].subject,
+            "O=Test, CN=Test Content Certificate 1-1"
+        );
+        assert_eq!(report.certificates[1].issuer, "O=Test, CN=Test Root CA 1");
+    }
+
+    #[test]
+    fn test_inspect_package_certificates() {
+        let path = "rsrc/packages/mac/isolated-photoshop/ngl-preconditioning-data.json";
+        let info = FileInfo::from_path(path).unwrap();
+        let data = json_from_file(&info).unwrap();
+        let mut certificates =
+            CertificateStore::from_preconditioning_json(&data).unwrap();
+        certificates.add("broken.der", b"not a certificate".to_vec());
+        let report = certificates.inspect();
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].pathname, "broken.der");
+        let cert = report
+            .certificates
+            .iter()
+            .find(|cert| cert.subject.contains("Adobe Content Certificate 42-6"))
+            .unwrap();
+        assert_eq!(cert.serial_number, "3bd6e09e");
+        assert_eq!(
+            cert.sha1_fingerprint,
+            "C18CFD9CF83D2B6D1A270B2641EBE6C8F73DB818"
+        );
+        assert_eq!(cert.sha256_fingerprint.len(), 64);
+        let now = Local.ymd(2021, 1, 1).and_hms(0, 0, 0);
+        assert_eq!(cert.problem(now, None), None);
+        let later = Local.ymd(2080, 1, 1).and_hms(0, 0, 0);
+        assert_eq!(
+            cert.problem(now, Some(later)),
+            Some(CertificateProblem::ExpiresBeforeLicense)
+        );
+        assert_eq!(cert.problem(later, None), Some(CertificateProblem::Expired));
+    }
+
+    #[test]
+    fn test_certificate_errors() {
+        let path = "rsrc/packages/mac/lan-illustrator/ngl-preconditioning-data.json";
+        let info = FileInfo::from_path(path).unwrap();
+        let mut data: JsonMap = json_from_file(&info).unwrap();
+        let certs = data
+            .get_mut("certificates")
+            .unwrap()
+            .as_array_mut()
+            .unwrap();
+        let count = certs.len();
+        certs[0]["content"] = "not base64!".into();
+        let certificates = CertificateStore::from_preconditioning_json(&data).unwrap();
+        // the broken certificate doesn't stop the others from loading
+        assert_eq!(certificates.len(), count - 1);
+        let report = certificates.inspect();
+        assert_eq!(report.errors.len(), 1);
+        assert!(format!("{:#}", report.errors[0].error)
+            .starts_with("Invalid certificate data"));
+        // serial numbers keep their leading zero bytes
+        let cert = report
+            .certificates
+            .iter()
+            .find(|cert| cert.subject.contains("Adobe Content Certificate 52-5"))
+            .unwrap();
+        assert_eq!(cert.serial_number, "02747e45");
+    }
+
+    #[test]
+    fn test_signed_license_expiries() {
+        let path = "rsrc/packages/mac/isolated-photoshop/ngl-preconditioning-data.json";
+        let info = FileInfo::from_path(path).unwrap();
+        let data = json_from_file(&info).unwrap();
+        let mut ocs = OperatingConfig::from_preconditioning_json(&data).unwrap();
+        let expiries = signed_license_expiries(&ocs);
+        // both licenses name the same four certificates in their license
+        // signatures, and another four in their customer signatures
+        assert_eq!(expiries.len(), 8);
+        let signer = &expiries["ACF138FEE5E2CF52298B8319C6B709230235EF36"];
+        assert_eq!(
+            Some(*signer),
+            ocs.iter().filter_map(|oc| oc.expiry_time).max()
+        );
+        // only the licenses a certificate signs count
+        let later = Local.ymd(2080, 1, 1).and_hms(0, 0, 0);
+        ocs[0].envelope.signatures.clear();
+        ocs[0].payload.asnp_data = None;
+        ocs[0].expiry_time = Some(later);
+        assert_eq!(signed_license_expiries(&ocs), expiries);
+    }
+}
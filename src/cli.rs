@@ -6,6 +6,7 @@ NOTICE: Adobe permits you to use, modify, and distribute this file in
 accordance with the terms of the Adobe license agreement accompanying
 it.
 */
+use std::str::FromStr;
 use structopt::StructOpt;
 
 pub const DEFAULT_CONFIG_DIR: &str = if cfg!(target_os = "macos") {
@@ -29,15 +30,205 @@ pub struct Opt {
     #[structopt(short, long, parse(from_occurrences))]
     pub verbose: i32,
 
+    /// Output format for the report: text, json, csv, or table.
+    /// The json format is documented in the README.  The csv and
+    /// table formats give one row per license file.
+    #[structopt(
+        long,
+        default_value = "text",
+        possible_values = &["text", "json", "csv", "table"],
+        case_insensitive = true
+    )]
+    pub format: Format,
+
+    /// Add a report showing, for each application, which of the installed
+    /// licenses it will use and which licenses are shadowed.
+    /// (The json format always includes this report.)
+    #[structopt(long)]
+    pub effective: bool,
+
+    /// Add a report showing, for each license that doesn't get its profile
+    /// from a server, the licensed product and features.  Serial keys are
+    /// masked unless --show-serial-keys is also given.
+    #[structopt(long)]
+    pub features: bool,
+
+    /// Show serial keys in full, rather than masked, in the features report.
+    #[structopt(long, requires = "features")]
+    pub show_serial_keys: bool,
+
+    /// Add a report listing the certificates shipped with the licenses
+    /// (in preconditioning data, or as .der files next to installed
+    /// license files), warning about any that have expired or that
+    /// expire before the licenses do.
+    #[structopt(long)]
+    pub certificates: bool,
+
+    /// Verify the signatures in each license, and report whether they are
+    /// valid, invalid, or untrusted.  The certificates needed are taken from
+    /// the preconditioning data or, for installed licenses, from the .der
+    /// files in the same directory as the license files.  No license is
+    /// valid unless --trusted-roots names the roots to trust.
+    #[structopt(long)]
+    pub verify: bool,
+
+    /// Connect to the license servers named in FRL LAN licenses (and in
+    /// FRL Online licenses that use a proxy), and report whether they can
+    /// be reached, how long that takes, and whether each server presents
+    /// the certificate that its licenses expect.
+    #[structopt(long)]
+    pub probe: bool,
+
+    /// A directory of root certificates (.der files) to trust when verifying
+    /// signatures.  This is required for a license to verify as valid:
+    /// root certificates are not shipped with licenses, and the decoder
+    /// doesn't have Adobe's roots, so without this option every signature
+    /// stops at an untrusted root.
+    #[structopt(long, value_name = "DIR", requires = "verify")]
+    pub trusted_roots: Option<String>,
+
+    /// Check a package (preconditioning data or a ccp file) for internal
+    /// consistency instead of producing a report, listing each problem
+    /// found along with where it is in the package.  Exits with status 1
+    /// if any problems are found.
+    #[structopt(long, conflicts_with_all = &["warn-days", "critical-days"])]
+    pub lint: bool,
+
+    /// Look for licenses in every directory in the tree under the given
+    /// directory (not just in the directory itself), decoding every license
+    /// file, preconditioning file, and ccp file found, and report them
+    /// grouped by the directory they came from.
+    #[structopt(long, conflicts_with_all = &["lint", "warn-days", "critical-days"])]
+    pub recursive: bool,
+
+    /// Check license expiry dates instead of producing a report, and
+    /// warn about any that are this many days or fewer away.  Prints
+    /// a one-line summary and exits with a Nagios-style status code
+    /// (0 = OK, 1 = WARNING, 2 = CRITICAL, 3 = UNKNOWN).  Specify -vv
+    /// to check cached activations as well as licenses.
+    #[structopt(long, value_name = "N")]
+    pub warn_days: Option<i64>,
+
+    /// Check license expiry dates (as with --warn-days), and treat any
+    /// that are this many days or fewer away as critical.  Expiry dates
+    /// that have already passed are always critical.
+    #[structopt(long, value_name = "M")]
+    pub critical_days: Option<i64>,
+
+    /// Columns to include in csv and table output, separated by commas.
+    /// Choose from: filename, npdId, appId, certGroupId, mode, server,
+    /// censusCodes, precedence, expiryDate, installDate (the default is all).
+    #[structopt(long, use_delimiter = true)]
+    pub columns: Vec<Column>,
+
     /// path to directory or file to decode
     #[structopt(default_value = DEFAULT_CONFIG_DIR)]
     pub path: String,
 }
 
+impl Opt {
+    /// Whether expiry monitoring was requested instead of a report.
+    pub fn is_monitoring(&self) -> bool {
+        self.warn_days.is_some() || self.critical_days.is_some()
+    }
+
+    /// Check the options that can't be checked one at a time.
+    pub fn validate(&self) -> Result<(), String> {
+        if let (Some(warn), Some(critical)) = (self.warn_days, self.critical_days) {
+            if critical > warn {
+                return Err(format!(
+                    "--critical-days ({}) can't be more than --warn-days ({})",
+                    critical, warn
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+    Csv,
+    Table,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "table" => Ok(Format::Table),
+            _ => Err(format!("Unknown output format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Filename,
+    NpdId,
+    AppId,
+    CertGroupId,
+    Mode,
+    Server,
+    CensusCodes,
+    Precedence,
+    ExpiryDate,
+    InstallDate,
+}
+
+impl Column {
+    pub const ALL: [Column; 10] = [
+        Column::Filename,
+        Column::NpdId,
+        Column::AppId,
+        Column::CertGroupId,
+        Column::Mode,
+        Column::Server,
+        Column::CensusCodes,
+        Column::Precedence,
+        Column::ExpiryDate,
+        Column::InstallDate,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Column::Filename => "filename",
+            Column::NpdId => "npdId",
+            Column::AppId => "appId",
+            Column::CertGroupId => "certGroupId",
+            Column::Mode => "mode",
+            Column::Server => "server",
+            Column::CensusCodes => "censusCodes",
+            Column::Precedence => "precedence",
+            Column::ExpiryDate => "expiryDate",
+            Column::InstallDate => "installDate",
+        }
+    }
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Column::ALL
+            .iter()
+            .find(|column| column.name().eq_ignore_ascii_case(s.trim()))
+            .copied()
+            .ok_or_else(|| format!("Unknown column: {}", s))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::DEFAULT_CONFIG_DIR;
-    use crate::utilities::FileInfo;
+    use super::{Column, Format, Opt, DEFAULT_CONFIG_DIR};
+    use adobe_license_decoder::FileInfo;
+    use structopt::StructOpt;
 
     #[test]
     fn test_os() {
@@ -52,4 +243,37 @@ mod tests {
             "Application Support path is not present"
         );
     }
+
+    #[test]
+    fn test_format_from_str() {
+        assert_eq!("text".parse::<Format>().unwrap(), Format::Text);
+        assert_eq!("JSON".parse::<Format>().unwrap(), Format::Json);
+        assert!("xml".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let opt = |args: &[&str]| {
+            Opt::from_iter(std::iter::once("decoder").chain(args.iter().copied()))
+        };
+        assert!(opt(&["--warn-days", "30", "--critical-days", "7"])
+            .validate()
+            .is_ok());
+        assert!(opt(&["--warn-days", "7", "--critical-days", "7"])
+            .validate()
+            .is_ok());
+        assert!(opt(&["--critical-days", "30"]).validate().is_ok());
+        let err = opt(&["--warn-days", "7", "--critical-days", "30"]).validate();
+        assert_eq!(
+            err.unwrap_err(),
+            "--critical-days (30) can't be more than --warn-days (7)"
+        );
+    }
+
+    #[test]
+    fn test_column_from_str() {
+        assert_eq!("npdId".parse::<Column>().unwrap(), Column::NpdId);
+        assert_eq!("EXPIRYDATE".parse::<Column>().unwrap(), Column::ExpiryDate);
+        assert!("colour".parse::<Column>().is_err());
+    }
 }
@@ -6,64 +6,855 @@ NOTICE: Adobe permits you to use, modify, and distribute this file in
 accordance with the terms of the Adobe license agreement accompanying
 it.
 */
-use crate::types::{DeploymentMode, OperatingConfig};
-use crate::utilities::{date_from_epoch_millis, shorten_oc_file_name, FileInfo};
-use eyre::{eyre, Result};
-use std::cmp::Ordering::Equal;
+use crate::cli::{Format, Opt};
+use crate::tables::{print_grouped_rows, print_rows};
+use adobe_license_decoder::analysis::{
+    effective_licenses, find_conflicts, ConflictKind, ShadowReason,
+};
+use adobe_license_decoder::asnp::{Asnp, PackageAsnps};
+use adobe_license_decoder::certificates::{
+    signed_license_expiries, CertificateInfo, CertificateProblem, CertificateReport,
+    CertificateStore,
+};
+use adobe_license_decoder::lint::{lint_preconditioning_json, Violation};
+use adobe_license_decoder::models::{SignatureHeader, SpecVersion};
+use adobe_license_decoder::monitoring::{
+    check_expiry, overall_status, summary, Status, Thresholds,
+};
+use adobe_license_decoder::probe::{probe_targets, ProbeResult};
+use adobe_license_decoder::types::{preconditioning_json_from_ccp, Environment};
+use adobe_license_decoder::types::{CustomerTerms, LicenseFeatures, LicenseTerms};
+use adobe_license_decoder::utilities::{
+    date_from_epoch_millis, json_from_file, license_directories, shorten_oc_file_name,
+    JsonMap,
+};
+use adobe_license_decoder::verification::{verify_license, Verification};
+use adobe_license_decoder::{DeploymentMode, FileError, FileInfo, OperatingConfig};
+use eyre::{eyre, Result, WrapErr};
+use serde::Serialize;
+use std::path::Path;
+use std::time::Duration;
 
-pub fn describe_directory(info: &FileInfo, verbose: i32) -> Result<()> {
-    let json_file = format!("{}/ngl-preconditioning-data.json", info.pathname);
-    if let Ok(info) = FileInfo::from_path(&json_file) {
-        return describe_file(&info, verbose);
-    }
-    let pattern = format!("{}/*.ccp", info.pathname);
-    for path in glob::glob(&pattern).unwrap() {
-        if let Ok(info) = FileInfo::from_path(path.unwrap().to_str().unwrap()) {
-            return describe_file(&info, verbose);
-        }
+/// How long to wait for each step of connecting to a license server.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+enum Source {
+    PreconditioningData,
+    LicenseFiles,
+}
+
+/// Licenses decoded from a file or directory, along with
+/// the files shipped with them.
+struct Decoded {
+    source: Source,
+    info: FileInfo,
+    ocs: Vec<OperatingConfig>,
+    /// The license (or ASNP) files that could not be decoded.
+    errors: Vec<FileError>,
+    bundled: Bundled,
+}
+
+/// The licenses decoded from the files in a directory.
+struct DirectoryGroup {
+    path: String,
+    decoded: Vec<Decoded>,
+    /// The package files that could not be decoded at all.
+    errors: Vec<FileError>,
+}
+
+impl DirectoryGroup {
+    fn all_errors(&self) -> impl Iterator<Item = &FileError> {
+        let decoded = self.decoded.iter().flat_map(|decoded| &decoded.errors);
+        decoded.chain(&self.errors)
     }
-    let pattern = format!("{}/*.operatingconfig", info.pathname);
-    let mut ocs: Vec<OperatingConfig> = Vec::new();
-    for path in glob::glob(&pattern).unwrap() {
-        if let Ok(info) = FileInfo::from_path(path.unwrap().to_str().unwrap()) {
-            let oc = OperatingConfig::from_license_file(&info)?;
-            ocs.push(oc)
+}
+
+/// Describe the licenses in a directory, returning the exit status.
+/// All the package files and license files in the directory are
+/// decoded, and if there's more than one of them, each report
+/// says where its licenses came from.
+pub fn describe_directory(info: &FileInfo, opt: &Opt) -> Result<i32> {
+    if opt.lint {
+        let checked = lint_directory(&info.pathname)?;
+        if checked.is_empty() {
+            return Err(eyre!("No package to check in directory: {}", info.pathname));
         }
+        return report_lint(&checked, opt);
     }
-    if ocs.is_empty() {
-        Err(eyre!(
+    let mut group = decode_directory(&info.pathname, opt);
+    if group.decoded.is_empty() && group.errors.is_empty() {
+        return Err(eyre!(
             "No license files found in directory: {}",
             info.pathname
-        ))
+        ));
+    }
+    // when nothing could be decoded, that's an error rather than a partial failure
+    let licenses: usize = group.decoded.iter().map(|d| d.ocs.len()).sum();
+    if licenses == 0 && group.all_errors().next().is_some() {
+        if group.decoded.is_empty() && group.errors.len() == 1 {
+            return Err(group.errors.remove(0).error);
+        }
+        describe_errors(group.all_errors(), true);
+        return Err(eyre!(
+            "None of the license files in directory could be decoded: {}",
+            info.pathname
+        ));
+    }
+    // JSON reports on directories have the same shape however many files they hold
+    if group.decoded.len() == 1 && group.errors.is_empty() && opt.format != Format::Json {
+        report(&group.decoded[0], opt)
     } else {
-        ocs.sort_by(|oc1, oc2| match oc1.npd_id.cmp(&oc2.npd_id) {
-            Equal => oc1.app_id.cmp(&oc2.app_id),
-            otherwise => otherwise,
+        report_group(&group, opt)
+    }
+}
+
+/// Describe the licenses in every directory of a tree, grouped by
+/// the directory they came from, returning the exit status.
+pub fn describe_tree(info: &FileInfo, opt: &Opt) -> Result<i32> {
+    let groups = decode_tree(&info.pathname, opt)?;
+    if groups.is_empty() {
+        return Err(eyre!(
+            "No license files found in directory tree: {}",
+            info.pathname
+        ));
+    }
+    match opt.format {
+        Format::Text => {
+            for group in &groups {
+                println!("Directory: {}", &group.path);
+                describe_group(group, opt)?;
+            }
+        }
+        Format::Json => {
+            let mut group_findings = Vec::new();
+            for group in &groups {
+                group_findings.push(findings_for_group(group, opt)?);
+            }
+            let mut directories = Vec::new();
+            for (group, findings) in groups.iter().zip(&group_findings) {
+                directories.push(json_directory(group, findings, opt)?);
+            }
+            let report = JsonTreeReport {
+                path: &info.pathname,
+                directories,
+            };
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Format::Csv | Format::Table => {
+            let groups: Vec<(&str, &[OperatingConfig])> = groups
+                .iter()
+                .flat_map(|group| {
+                    let path = group.path.as_str();
+                    group.decoded.iter().map(move |d| (path, d.ocs.as_slice()))
+                })
+                .collect();
+            print_grouped_rows("directory", &groups, &opt.columns, opt.format)?;
+        }
+    }
+    if opt.format == Format::Csv || opt.format == Format::Table {
+        describe_errors(groups.iter().flat_map(|g| g.all_errors()), true);
+    }
+    let failed = groups
+        .iter()
+        .any(|group| group.all_errors().next().is_some());
+    Ok(if failed { 2 } else { 0 })
+}
+
+/// Decode each of the directories in a tree that has license files,
+/// in order of their path.  Directories that can't be read are
+/// listed with the error found when reading them.
+fn decode_tree(root: &str, opt: &Opt) -> Result<Vec<DirectoryGroup>> {
+    let (directories, errors) = license_directories(root)?;
+    let mut groups: Vec<DirectoryGroup> = directories
+        .iter()
+        .map(|path| decode_directory(path, opt))
+        .collect();
+    for error in errors {
+        groups.push(DirectoryGroup {
+            path: error.pathname.clone(),
+            decoded: Vec::new(),
+            errors: vec![error],
         });
-        describe_operating_configs(&ocs, verbose)
     }
+    groups.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(groups)
+}
+
+/// Describe the licenses in a file, returning the exit status.
+pub fn describe_file(info: &FileInfo, opt: &Opt) -> Result<i32> {
+    if opt.lint {
+        return match read_package(info)? {
+            Some(data) => {
+                let checked = (info.pathname.clone(), lint_preconditioning_json(&data));
+                report_lint(&[checked], opt)
+            }
+            None => Err(eyre!("Not a package: {}", info.pathname)),
+        };
+    }
+    report(&decode_file(info, opt)?, opt)
 }
 
-pub fn describe_file(info: &FileInfo, verbose: i32) -> Result<()> {
+/// Check each of the package files in a directory for consistency.
+/// Package files that can't be read are reported as a problem with
+/// the whole file, rather than stopping the others from being checked.
+fn lint_directory(path: &str) -> Result<Vec<(String, Vec<Violation>)>> {
+    let mut checked = Vec::new();
+    for pathname in package_files(path)? {
+        let info = FileInfo::from_path(&pathname)?;
+        let violations = match read_package(&info) {
+            Ok(data) => lint_preconditioning_json(&data.unwrap_or_default()),
+            Err(error) => vec![Violation {
+                location: info.filename.clone(),
+                message: format!("{:#}", error),
+            }],
+        };
+        checked.push((pathname, violations));
+    }
+    Ok(checked)
+}
+
+/// The package files (preconditioning data and ccp files) in a directory.
+fn package_files(path: &str) -> Result<Vec<String>> {
+    let mut pathnames = Vec::new();
+    let json_file = format!("{}/ngl-preconditioning-data.json", path);
+    if Path::new(&json_file).is_file() {
+        pathnames.push(json_file);
+    }
+    let pattern = format!("{}/*.ccp", glob::Pattern::escape(path));
+    for path in glob::glob(&pattern)? {
+        pathnames.push(path?.to_string_lossy().to_string());
+    }
+    Ok(pathnames)
+}
+
+/// Decode each of the package files in a directory, and then its
+/// license files.  Package files that can't be decoded don't stop
+/// the others from being decoded.
+fn decode_directory(path: &str, opt: &Opt) -> DirectoryGroup {
+    let mut group = DirectoryGroup {
+        path: path.to_string(),
+        decoded: Vec::new(),
+        errors: Vec::new(),
+    };
+    match package_files(path) {
+        Ok(pathnames) => {
+            for pathname in pathnames {
+                let info = FileInfo::from_path(&pathname);
+                match info.and_then(|info| decode_file(&info, opt)) {
+                    Ok(decoded) => group.decoded.push(decoded),
+                    Err(error) => group.errors.push(FileError { pathname, error }),
+                }
+            }
+        }
+        Err(error) => group.errors.push(FileError {
+            pathname: path.to_string(),
+            error: error.wrap_err("Can't list package files"),
+        }),
+    }
+    match FileInfo::from_path(path).and_then(|info| decode_license_files(&info, opt)) {
+        Ok(decoded) if decoded.ocs.is_empty() && decoded.errors.is_empty() => {}
+        Ok(decoded) => group.decoded.push(decoded),
+        Err(error) => group.errors.push(FileError {
+            pathname: path.to_string(),
+            error,
+        }),
+    }
+    group
+}
+
+/// The preconditioning data in a package file, or `None`
+/// if the file isn't a package.
+fn read_package(info: &FileInfo) -> Result<Option<JsonMap>> {
     if info.extension.eq_ignore_ascii_case("json") {
-        let mut ocs = OperatingConfig::from_preconditioning_file(info)?;
-        ocs.sort_by(|oc1, oc2| oc1.app_id.cmp(&oc2.app_id));
-        describe_preconditioning_data(&ocs, verbose);
-        Ok(())
+        Ok(Some(json_from_file(info)?))
     } else if info.extension.eq_ignore_ascii_case("ccp") {
-        let mut ocs = OperatingConfig::from_ccp_file(info)?;
-        ocs.sort_by(|oc1, oc2| oc1.app_id.cmp(&oc2.app_id));
-        describe_preconditioning_data(&ocs, verbose);
-        Ok(())
+        let bytes = std::fs::read(&info.pathname).wrap_err("Cannot read ccp file")?;
+        Ok(Some(preconditioning_json_from_ccp(&bytes)?))
+    } else {
+        Ok(None)
+    }
+}
+
+fn decode_file(info: &FileInfo, opt: &Opt) -> Result<Decoded> {
+    if let Some(data) = read_package(info)? {
+        decode_preconditioning_json(info, &data, opt)
     } else if info.extension.eq_ignore_ascii_case("operatingconfig") {
         let oc = OperatingConfig::from_license_file(info)?;
-        describe_operating_configs(&vec![oc], verbose)
+        let bundled = Bundled {
+            certificates: load_certificates(opt, || {
+                CertificateStore::from_file_directory(&info.pathname)
+            })?,
+            asnps: None,
+        };
+        Ok(Decoded {
+            source: Source::LicenseFiles,
+            info: info.clone(),
+            ocs: vec![oc],
+            errors: Vec::new(),
+            bundled,
+        })
     } else {
         Err(eyre!("Not a license file: {}", info.pathname))
     }
 }
 
+fn decode_preconditioning_json(
+    info: &FileInfo, data: &JsonMap, opt: &Opt,
+) -> Result<Decoded> {
+    let ocs = OperatingConfig::from_preconditioning_json(data)?;
+    let asnps = PackageAsnps::from_preconditioning_json(data, &ocs)?;
+    let bundled = Bundled {
+        certificates: load_certificates(opt, || {
+            CertificateStore::from_preconditioning_json(data)
+        })?,
+        asnps: Some(asnps),
+    };
+    Ok(Decoded {
+        source: Source::PreconditioningData,
+        info: info.clone(),
+        ocs,
+        errors: Vec::new(),
+        bundled,
+    })
+}
+
+fn decode_license_files(info: &FileInfo, opt: &Opt) -> Result<Decoded> {
+    let decoded = OperatingConfig::from_directory(info)?;
+    let bundled = Bundled {
+        certificates: load_certificates(opt, || {
+            CertificateStore::from_directory(&info.pathname)
+        })?,
+        asnps: None,
+    };
+    Ok(Decoded {
+        source: Source::LicenseFiles,
+        info: info.clone(),
+        ocs: decoded.licenses,
+        errors: decoded.errors,
+        bundled,
+    })
+}
+
+/// The files shipped with the licenses being described.
+struct Bundled {
+    certificates: CertificateStore,
+    /// Only preconditioning data has ASNP files.
+    asnps: Option<PackageAsnps>,
+}
+
+/// The certificates shipped with the licenses, which are only
+/// loaded if they are needed for the report.
+fn load_certificates(
+    opt: &Opt, load: impl FnOnce() -> Result<CertificateStore>,
+) -> Result<CertificateStore> {
+    if opt.verify || opt.certificates {
+        load()
+    } else {
+        Ok(CertificateStore::default())
+    }
+}
+
+/// The optional parts of a report, which are only
+/// worked out if they are asked for.
+struct Findings {
+    verifications: Option<Vec<Verification>>,
+    certificates: Option<CertificateReport>,
+    probes: Option<Vec<ProbeResult>>,
+}
+
+fn findings(decoded: &Decoded, opt: &Opt) -> Result<Findings> {
+    let (ocs, bundled) = (&decoded.ocs, &decoded.bundled);
+    let verifications = if opt.verify {
+        let roots = match &opt.trusted_roots {
+            Some(path) => {
+                let roots = FileInfo::from_path(path)
+                    .wrap_err_with(|| format!("No such directory: {}", path))?;
+                CertificateStore::from_directory(&roots.pathname)?
+            }
+            None => CertificateStore::default(),
+        };
+        let verify = |oc| verify_license(oc, &bundled.certificates, &roots);
+        Some(ocs.iter().map(verify).collect::<Vec<Verification>>())
+    } else {
+        None
+    };
+    let certificates = if opt.certificates {
+        Some(bundled.certificates.inspect())
+    } else {
+        None
+    };
+    let probes = if opt.probe {
+        let targets = probe_targets(ocs);
+        Some(
+            targets
+                .iter()
+                .map(|t| t.probe(PROBE_TIMEOUT))
+                .collect::<Vec<_>>(),
+        )
+    } else {
+        None
+    };
+    Ok(Findings {
+        verifications,
+        certificates,
+        probes,
+    })
+}
+
+fn report(decoded: &Decoded, opt: &Opt) -> Result<i32> {
+    let (ocs, errors) = (&decoded.ocs, &decoded.errors);
+    if opt.is_monitoring() {
+        let decoded = std::slice::from_ref(decoded);
+        return Ok(report_expiry(decoded, errors.len(), opt));
+    }
+    let findings = findings(decoded, opt)?;
+    match opt.format {
+        Format::Text => describe_text(decoded, &findings, opt)?,
+        Format::Json => {
+            let report = JsonDirectory {
+                path: &decoded.info.pathname,
+                reports: vec![json_report(decoded, &findings, opt)?],
+                errors: Vec::new(),
+            };
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Format::Csv | Format::Table => {
+            print_rows(ocs, &opt.columns, opt.format)?;
+            describe_errors(errors, true);
+        }
+    }
+    // partial failures exit with a different status than total failures
+    Ok(if errors.is_empty() { 0 } else { 2 })
+}
+
+fn describe_text(decoded: &Decoded, findings: &Findings, opt: &Opt) -> Result<()> {
+    let ocs = &decoded.ocs;
+    match decoded.source {
+        Source::PreconditioningData => describe_preconditioning_data(ocs, opt.verbose),
+        Source::LicenseFiles => describe_operating_configs(ocs, opt.verbose)?,
+    }
+    if let Some(asnps) = &decoded.bundled.asnps {
+        describe_asnps(ocs, asnps, opt.verbose);
+    }
+    if opt.effective {
+        describe_effective_licenses(ocs);
+    }
+    if opt.features {
+        describe_features(ocs, opt.show_serial_keys);
+    }
+    describe_conflicts(ocs);
+    if let Some(verifications) = &findings.verifications {
+        describe_verifications(ocs, verifications);
+    }
+    if let Some(certificates) = &findings.certificates {
+        describe_certificates(ocs, certificates);
+    }
+    if let Some(probes) = &findings.probes {
+        describe_probes(probes);
+    }
+    describe_errors(&decoded.errors, false);
+    Ok(())
+}
+
+fn describe_errors<'a>(errors: impl IntoIterator<Item = &'a FileError>, to_stderr: bool) {
+    let lines: Vec<String> = errors
+        .into_iter()
+        .map(|e| format!("    {}: {:#}", &e.pathname, e.error))
+        .collect();
+    if lines.is_empty() {
+        return;
+    }
+    let message = format!("Unreadable license files:\n{}", lines.join("\n"));
+    if to_stderr {
+        eprintln!("{}", message)
+    } else {
+        println!("{}", message)
+    }
+}
+
+/// Describe the licenses decoded from the files in a directory
+/// together, returning the exit status.
+fn report_group(group: &DirectoryGroup, opt: &Opt) -> Result<i32> {
+    let failures = group.all_errors().count();
+    if opt.is_monitoring() {
+        return Ok(report_expiry(&group.decoded, failures, opt));
+    }
+    match opt.format {
+        Format::Text => describe_group(group, opt)?,
+        Format::Json => {
+            let findings = findings_for_group(group, opt)?;
+            let report = json_directory(group, &findings, opt)?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Format::Csv | Format::Table => {
+            let groups: Vec<(&str, &[OperatingConfig])> = group
+                .decoded
+                .iter()
+                .map(|d| (d.info.pathname.as_str(), d.ocs.as_slice()))
+                .collect();
+            print_grouped_rows("origin", &groups, &opt.columns, opt.format)?;
+            describe_errors(group.all_errors(), true);
+        }
+    }
+    Ok(if failures == 0 { 0 } else { 2 })
+}
+
+/// Describe each of the files decoded from a directory, starting
+/// each report with where its licenses came from.
+fn describe_group(group: &DirectoryGroup, opt: &Opt) -> Result<()> {
+    for decoded in &group.decoded {
+        match decoded.source {
+            Source::LicenseFiles if decoded.info.is_directory => {
+                println!("Origin: license files in {}", &decoded.info.pathname)
+            }
+            _ => println!("Origin: {}", &decoded.info.pathname),
+        }
+        describe_text(decoded, &findings(decoded, opt)?, opt)?;
+    }
+    describe_errors(&group.errors, false);
+    Ok(())
+}
+
+fn findings_for_group(group: &DirectoryGroup, opt: &Opt) -> Result<Vec<Findings>> {
+    group
+        .decoded
+        .iter()
+        .map(|decoded| findings(decoded, opt))
+        .collect()
+}
+
+fn json_directory<'a>(
+    group: &'a DirectoryGroup, findings: &'a [Findings], opt: &Opt,
+) -> Result<JsonDirectory<'a>> {
+    let mut reports = Vec::new();
+    for (decoded, findings) in group.decoded.iter().zip(findings) {
+        reports.push(json_report(decoded, findings, opt)?);
+    }
+    Ok(JsonDirectory {
+        path: &group.path,
+        reports,
+        errors: json_file_errors(&group.errors),
+    })
+}
+
+/// Report the problems found in each package, returning the exit status.
+fn report_lint(checked: &[(String, Vec<Violation>)], opt: &Opt) -> Result<i32> {
+    if opt.format == Format::Json {
+        // a list, even when only one package was checked
+        let reports: Vec<JsonLintReport> = checked
+            .iter()
+            .map(|(path, violations)| JsonLintReport { path, violations })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        for (path, violations) in checked {
+            if violations.is_empty() {
+                println!("No problems found in package: {}", path);
+            } else {
+                println!("Problems found in package: {}", path);
+                for violation in violations {
+                    println!("    {}", violation);
+                }
+            }
+        }
+    }
+    let clean = checked.iter().all(|(_, violations)| violations.is_empty());
+    Ok(if clean { 0 } else { 1 })
+}
+
+/// Check the expiry of the licenses decoded from one or more
+/// files, given how many files could not be decoded, returning
+/// the exit status.
+fn report_expiry(decoded: &[Decoded], failures: usize, opt: &Opt) -> i32 {
+    let thresholds = Thresholds {
+        warn_days: opt.warn_days,
+        critical_days: opt.critical_days,
+    };
+    let (now, cached) = (chrono::Local::now(), opt.verbose > 1);
+    let checks: Vec<_> = decoded
+        .iter()
+        .flat_map(|decoded| check_expiry(&decoded.ocs, now, &thresholds, cached))
+        .collect();
+    let mut status = overall_status(&checks);
+    if failures == 0 {
+        println!("{}", summary(&checks));
+    } else {
+        // files that can't be checked make the status UNKNOWN, unless it's CRITICAL
+        if status != Status::Critical {
+            status = Status::Unknown;
+        }
+        let summary = summary(&checks);
+        let details = summary.split_once(" - ").map_or("", |(_, details)| details);
+        println!(
+            "{} - {}; {} license files could not be decoded",
+            status, details, failures
+        );
+    }
+    status as i32
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonReport<'a> {
+    source: Source,
+    path: &'a str,
+    operating_configs: Vec<JsonOperatingConfig<'a>>,
+    effective_licenses: Vec<JsonEffectiveLicense<'a>>,
+    conflicts: Vec<JsonConflict<'a>>,
+    errors: Vec<JsonFileError<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    asnps: Option<&'a [Asnp]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    licenses_without_asnp: Option<Vec<&'a str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    asnp_errors: Option<Vec<JsonFileError<'a>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    certificates: Option<Vec<JsonCertificate<'a>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    certificate_errors: Option<Vec<JsonFileError<'a>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server_probes: Option<&'a [ProbeResult]>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonLintReport<'a> {
+    path: &'a str,
+    violations: &'a [Violation],
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonTreeReport<'a> {
+    path: &'a str,
+    directories: Vec<JsonDirectory<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonDirectory<'a> {
+    path: &'a str,
+    reports: Vec<JsonReport<'a>>,
+    errors: Vec<JsonFileError<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonOperatingConfig<'a> {
+    #[serde(flatten)]
+    oc: &'a OperatingConfig,
+    cached_activation_expiry: Option<String>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    device_params: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license_terms: Option<LicenseTerms>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license_features: Option<LicenseFeatures>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    customer_values: Option<CustomerTerms>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    customer_value_mismatches: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    filename_mismatches: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    spec_version_warnings: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature_headers: Option<JsonSignatureHeaders>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature_verification: Option<&'a Verification>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    detail_errors: Vec<String>,
+}
+
+/// Each set of headers is null if it can't be decoded,
+/// in which case the error is in the license's detail errors.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonSignatureHeaders {
+    license: Option<Vec<SignatureHeader>>,
+    customer: Option<Vec<SignatureHeader>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonEffectiveLicense<'a> {
+    app_id: &'a str,
+    filename: &'a str,
+    npd_id: &'a str,
+    shadowed: Vec<JsonShadowedLicense<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonShadowedLicense<'a> {
+    filename: &'a str,
+    npd_id: &'a str,
+    reason: ShadowReason,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonConflict<'a> {
+    kind: ConflictKind,
+    app_id: String,
+    filenames: Vec<&'a str>,
+    consequence: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonCertificate<'a> {
+    #[serde(flatten)]
+    info: &'a CertificateInfo,
+    problem: Option<CertificateProblem>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonFileError<'a> {
+    path: &'a str,
+    error: String,
+}
+
+fn json_report<'a>(
+    decoded: &'a Decoded, findings: &'a Findings, opt: &Opt,
+) -> Result<JsonReport<'a>> {
+    let (ocs, verbose) = (&decoded.ocs, opt.verbose);
+    let asnps = decoded.bundled.asnps.as_ref();
+    let verifications = findings.verifications.as_deref();
+    let certificates = findings.certificates.as_ref();
+    let mut operating_configs = Vec::new();
+    for (i, oc) in ocs.iter().enumerate() {
+        // if -vv is given, check for locally cached licenses
+        let mut cached_activation_expiry = None;
+        if verbose > 1 {
+            if let Ok(date) = oc.get_cached_expiry() {
+                cached_activation_expiry = Some(date_from_epoch_millis(&date)?)
+            }
+        }
+        // if -v is given, show the license terms and the certificates
+        // that signed the license
+        let mut license_terms = None;
+        let mut customer_values = None;
+        let mut signature_headers = None;
+        let mut detail_errors = Vec::new();
+        if verbose > 0 {
+            license_terms = detail(oc.license_terms(), &mut detail_errors).flatten();
+            customer_values = detail(oc.customer_terms(), &mut detail_errors).flatten();
+            let license = oc.envelope.decode_signature_headers();
+            let customer = oc.payload.decode_customer_cert_headers();
+            signature_headers = Some(JsonSignatureHeaders {
+                license: detail(license, &mut detail_errors),
+                customer: detail(customer, &mut detail_errors),
+            });
+        }
+        let mut features = None;
+        if opt.features {
+            let result = license_features(oc, opt.show_serial_keys);
+            features = detail(result, &mut detail_errors).flatten();
+        }
+        operating_configs.push(JsonOperatingConfig {
+            oc,
+            cached_activation_expiry,
+            device_params: oc.device_params(),
+            license_terms,
+            license_features: features,
+            customer_values,
+            customer_value_mismatches: oc.customer_value_mismatches(),
+            filename_mismatches: oc.filename_mismatches(),
+            spec_version_warnings: oc.spec_version_warnings(),
+            signature_headers,
+            signature_verification: verifications.map(|v| &v[i]),
+            detail_errors,
+        })
+    }
+    let effective_licenses = effective_licenses(ocs)
+        .into_iter()
+        .map(|el| JsonEffectiveLicense {
+            app_id: &el.effective.app_id,
+            filename: &el.effective.filename,
+            npd_id: &el.effective.npd_id,
+            shadowed: el
+                .shadowed
+                .iter()
+                .map(|sh| JsonShadowedLicense {
+                    filename: &sh.license.filename,
+                    npd_id: &sh.license.npd_id,
+                    reason: sh.reason,
+                })
+                .collect(),
+        })
+        .collect();
+    let conflicts = find_conflicts(ocs)
+        .into_iter()
+        .map(|c| JsonConflict {
+            kind: c.kind,
+            app_id: c.app_id,
+            filenames: c.licenses.iter().map(|oc| oc.filename.as_str()).collect(),
+            consequence: c.consequence,
+        })
+        .collect();
+    let report = JsonReport {
+        source: decoded.source,
+        path: &decoded.info.pathname,
+        operating_configs,
+        effective_licenses,
+        conflicts,
+        errors: json_file_errors(&decoded.errors),
+        asnps: asnps.map(|package| package.asnps.as_slice()),
+        licenses_without_asnp: asnps.map(|package| {
+            let unmatched = package.licenses_without_asnp(ocs);
+            unmatched.iter().map(|oc| oc.filename.as_str()).collect()
+        }),
+        asnp_errors: asnps.map(|package| json_file_errors(&package.errors)),
+        certificates: certificates.map(|report| {
+            let (now, expiries) = (chrono::Local::now(), signed_license_expiries(ocs));
+            let certificate = |info: &'a CertificateInfo| JsonCertificate {
+                info,
+                problem: info.problem(now, expiries.get(&info.sha1_fingerprint).copied()),
+            };
+            report.certificates.iter().map(certificate).collect()
+        }),
+        certificate_errors: certificates.map(|r| json_file_errors(&r.errors)),
+        server_probes: findings.probes.as_deref(),
+    };
+    Ok(report)
+}
+
+/// A detail of a license for the json report, or `None` if it can't be
+/// decoded, in which case the error is added to the detail errors.
+fn detail<T>(result: Result<T>, errors: &mut Vec<String>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(err) => {
+            errors.push(format!("{:#}", err));
+            None
+        }
+    }
+}
+
+fn json_file_errors(errors: &[FileError]) -> Vec<JsonFileError<'_>> {
+    errors
+        .iter()
+        .map(|e| JsonFileError {
+            path: &e.pathname,
+            error: format!("{:#}", e.error),
+        })
+        .collect()
+}
+
 fn describe_operating_configs(ocs: &[OperatingConfig], verbose: i32) -> Result<()> {
+    let stage = ocs.iter().filter(|oc| oc.environment == Environment::Stage);
+    match stage.count() {
+        0 => {}
+        1 => println!("WARNING: a stage (non-production) license is installed!"),
+        n => println!(
+            "WARNING: {} stage (non-production) licenses are installed!",
+            n
+        ),
+    }
     let mut current_npd_id = "";
     for (i, oc) in ocs.iter().enumerate() {
         if !current_npd_id.eq_ignore_ascii_case(&oc.npd_id) {
@@ -75,6 +866,16 @@ fn describe_operating_configs(ocs: &[OperatingConfig], verbose: i32) -> Result<(
         println!("{: >2}: {}", i + 1, shorten_oc_file_name(&oc.filename)?);
         describe_app(-1, &oc.app_id, &oc.cert_group_id, verbose);
         println!("    Install date: {}", &oc.install_datetime);
+        if let Some(warning) = oc.envelope.spec_version().warning() {
+            println!("    Warning: {}", warning);
+        }
+        for mismatch in oc.filename_mismatches() {
+            println!("    Warning: {}", mismatch);
+        }
+        if verbose > 0 {
+            describe_signatures(oc, verbose);
+        }
+        describe_customer_values(oc, verbose);
         // if -vv is given, check for locally cached licenses
         if verbose > 1 {
             if let Ok(date) = oc.get_cached_expiry() {
@@ -90,6 +891,198 @@ fn describe_operating_configs(ocs: &[OperatingConfig], verbose: i32) -> Result<(
     Ok(())
 }
 
+fn describe_effective_licenses(ocs: &[OperatingConfig]) {
+    println!("Effective licenses:");
+    for el in effective_licenses(ocs) {
+        describe_app(-1, &el.app_id, &el.effective.cert_group_id, 0);
+        println!(
+            "        Uses npdId: {} (installed {})",
+            &el.effective.npd_id, &el.effective.install_datetime
+        );
+        for sh in el.shadowed {
+            println!(
+                "        Shadows npdId: {} ({})",
+                &sh.license.npd_id, sh.reason
+            );
+        }
+    }
+}
+
+fn describe_conflicts(ocs: &[OperatingConfig]) {
+    let conflicts = find_conflicts(ocs);
+    if conflicts.is_empty() {
+        return;
+    }
+    println!("Warnings:");
+    for conflict in conflicts {
+        println!("    {}: {}:", &conflict.app_id, conflict.kind);
+        for oc in &conflict.licenses {
+            println!("        {}", &oc.filename);
+        }
+        println!("        {}", &conflict.consequence);
+    }
+}
+
+fn describe_verifications(ocs: &[OperatingConfig], verifications: &[Verification]) {
+    println!("Signature verification:");
+    for (oc, verification) in ocs.iter().zip(verifications) {
+        println!("    {}: {}", &oc.filename, verification.status);
+        for problem in &verification.problems {
+            println!("        {}", problem);
+        }
+    }
+}
+
+/// The features of a license, with its serial keys masked
+/// unless they should be shown.
+fn license_features(
+    oc: &OperatingConfig, show_serial_keys: bool,
+) -> Result<Option<LicenseFeatures>> {
+    let mut features = oc.license_features()?;
+    if let (Some(features), false) = (&mut features, show_serial_keys) {
+        features.enigma_data = features.enigma_data.masked();
+    }
+    Ok(features)
+}
+
+fn describe_features(ocs: &[OperatingConfig], show_serial_keys: bool) {
+    println!("Licensed features:");
+    for oc in ocs {
+        describe_app(-1, &oc.app_id, &oc.cert_group_id, 0);
+        match license_features(oc, show_serial_keys) {
+            Ok(Some(features)) => {
+                let enigma = &features.enigma_data;
+                println!("        Product ID: {}", enigma.product_id);
+                if let Some(key) = &enigma.serial_key {
+                    println!("        Serial key: {}", key);
+                }
+                if let Some(key) = &enigma.clear_serial_key {
+                    println!("        Clear serial key: {}", key);
+                }
+                println!("        Features:");
+                for feature in &features.licensed_features {
+                    println!("            {}", feature);
+                }
+            }
+            Ok(None) => println!("        Features: controlled by server"),
+            Err(err) => println!("        Features: unreadable ({:#})", err),
+        }
+    }
+}
+
+fn describe_asnps(ocs: &[OperatingConfig], package: &PackageAsnps, verbose: i32) {
+    // if -v is given, show the license details in the ASNP files
+    if verbose > 0 && !package.asnps.is_empty() {
+        println!("License profiles (ASNP files):");
+        for asnp in &package.asnps {
+            describe_app(-1, &asnp.app_id, "", 0);
+            let license_id = asnp.license_id.as_deref();
+            println!(
+                "        License ID: {}",
+                license_id.unwrap_or("controlled by server")
+            );
+            let license_type = asnp
+                .license_type
+                .map_or("unknown".into(), |t| t.to_string());
+            match &asnp.activation_type {
+                Some(activation) => {
+                    println!(
+                        "        ASNP license type: {} ({})",
+                        license_type, activation
+                    )
+                }
+                None => println!("        ASNP license type: {}", license_type),
+            }
+            println!("        Effective end date: {}", &asnp.effective_end_date);
+            println!("        Spec version: {}", &asnp.spec_version);
+        }
+    }
+    for asnp in &package.asnps {
+        if let Some(warning) = asnp.spec_version_warning() {
+            println!("Warning: ASNP file {}: {}", &asnp.filename, warning);
+        }
+    }
+    let unmatched = package.licenses_without_asnp(ocs);
+    if !unmatched.is_empty() {
+        println!("Licenses without a matching ASNP file:");
+        for oc in unmatched {
+            println!("    {}", &oc.filename);
+        }
+    }
+    // unreadable ASNP files are only warned about, since the
+    // licenses that use them could still be decoded
+    if !package.errors.is_empty() {
+        println!("Unreadable ASNP files:");
+        for error in &package.errors {
+            println!("    {}: {:#}", &error.pathname, error.error);
+        }
+    }
+}
+
+fn describe_certificates(ocs: &[OperatingConfig], report: &CertificateReport) {
+    println!("Bundled certificates:");
+    if report.certificates.is_empty() && report.errors.is_empty() {
+        println!("    None found");
+    }
+    let (now, expiries) = (chrono::Local::now(), signed_license_expiries(ocs));
+    for cert in &report.certificates {
+        let license_expiry = expiries.get(&cert.sha1_fingerprint).copied();
+        println!("    {}", &cert.subject);
+        println!("        Issuer: {}", &cert.issuer);
+        println!("        Serial number: {}", &cert.serial_number);
+        println!(
+            "        Valid from {} until {}",
+            &cert.valid_from, &cert.valid_until
+        );
+        println!("        SHA-1 fingerprint: {}", &cert.sha1_fingerprint);
+        println!("        SHA-256 fingerprint: {}", &cert.sha256_fingerprint);
+        if let Some(problem) = cert.problem(now, license_expiry) {
+            println!("        Warning: certificate {}", problem);
+        }
+    }
+    for error in &report.errors {
+        println!("    {}: unreadable ({:#})", &error.pathname, error.error);
+    }
+}
+
+fn describe_probes(probes: &[ProbeResult]) {
+    if probes.is_empty() {
+        println!("No license servers to probe.");
+        return;
+    }
+    println!("License servers:");
+    for probe in probes {
+        let target = &probe.target;
+        println!(
+            "    {} (used by {})",
+            &target.server,
+            target.app_ids.join(", ")
+        );
+        if let Some(warning) = &probe.warning {
+            println!("        Warning: {}", warning);
+        }
+        if let Some(error) = &probe.error {
+            println!("        Unreachable: {}", error);
+            continue;
+        }
+        if let Some(millis) = probe.latency_millis {
+            println!("        Reachable in {} ms", millis);
+        }
+        if let Some(fingerprint) = &probe.certificate_fingerprint {
+            let subject = probe.certificate_subject.as_deref().unwrap_or("unreadable");
+            println!("        Certificate: {}", subject);
+            println!("        Certificate fingerprint: {}", fingerprint);
+        }
+        match probe.fingerprint_matches {
+            Some(true) => println!("        Certificate matches the licenses"),
+            Some(false) => println!(
+                "        Warning: the certificate doesn't match the fingerprint in the licenses"
+            ),
+            None => {}
+        }
+    }
+}
+
 fn describe_preconditioning_data(ocs: &[OperatingConfig], verbose: i32) {
     for (i, oc) in ocs.iter().enumerate() {
         if i == 0 {
@@ -98,14 +1091,129 @@ fn describe_preconditioning_data(ocs: &[OperatingConfig], verbose: i32) {
             println!("Application Licenses:")
         }
         describe_app(i as i32, &oc.app_id, &oc.cert_group_id, verbose);
+        if let Some(warning) = oc.envelope.spec_version().warning() {
+            println!("    Warning: {}", warning);
+        }
+        for mismatch in oc.filename_mismatches() {
+            println!("    Warning: {}", mismatch);
+        }
+        if verbose > 0 {
+            describe_signatures(oc, verbose);
+        }
+        describe_customer_values(oc, verbose);
     }
 }
 
+fn describe_signatures(oc: &OperatingConfig, verbose: i32) {
+    let describe = |kind: &str, headers: Result<Vec<SignatureHeader>>| match headers {
+        Ok(headers) if headers.is_empty() => {}
+        Ok(headers) => {
+            println!("    {} signatures:", kind);
+            for (i, header) in headers.iter().enumerate() {
+                println!("        {}: {}", i + 1, describe_signature_header(header));
+                // if -vv is given, show where to find each certificate
+                if verbose > 1 {
+                    for cert in header.chain() {
+                        println!(
+                            "            {}: id {}, SHA-1 hash {}, download path {}",
+                            &cert.subject_name,
+                            &cert.id,
+                            &cert.sha1_hash,
+                            &cert.download_path
+                        );
+                    }
+                }
+            }
+        }
+        Err(err) => println!("    {} signatures: unreadable ({:#})", kind, err),
+    };
+    describe("License", oc.envelope.decode_signature_headers());
+    describe("Customer", oc.payload.decode_customer_cert_headers());
+}
+
+fn describe_customer_values(oc: &OperatingConfig, verbose: i32) {
+    if verbose > 0 {
+        match oc.customer_terms() {
+            Ok(Some(terms)) => {
+                println!("    Customer-signed values:");
+                println!("        npdId: {}", &terms.npd_id);
+                println!("        ASNP ID: {}", &terms.asnp_id);
+                println!(
+                    "        Created: {}, response type: {}",
+                    &terms.creation_date, &terms.response_type
+                );
+                println!(
+                    "        Cache lifetime: {} (expires {})",
+                    &terms.cache_lifetime, &terms.cache_expiry_date
+                );
+                if let (Some(start), Some(interval)) = (
+                    &terms.cache_expiry_warning_start_date,
+                    &terms.cache_expiry_warning_interval,
+                ) {
+                    println!(
+                        "        Cache expiry warnings start: {}, every {}",
+                        start, interval
+                    );
+                }
+                println!(
+                    "        Challenge codes: {}",
+                    terms.challenge_codes.join(", ")
+                );
+                for (name, value) in &terms.other_values {
+                    println!("        {}: {}", name, value);
+                }
+            }
+            Ok(None) => {}
+            Err(err) => println!("    Customer-signed values: unreadable ({:#})", err),
+        }
+    }
+    for mismatch in oc.customer_value_mismatches() {
+        println!("    Warning: {}", mismatch);
+    }
+}
+
+fn describe_signature_header(header: &SignatureHeader) -> String {
+    let chain: Vec<String> = header
+        .chain()
+        .iter()
+        .map(|cert| {
+            format!(
+                "{} (serial {})",
+                &cert.subject_name, &cert.hex_serial_number
+            )
+        })
+        .collect();
+    let mut result = chain.join(", issued by ");
+    if let Some(index) = header.trusted_cert_fingerprint_index {
+        result.push_str(&format!("; trusted fingerprint index {}", index));
+    }
+    result
+}
+
 fn describe_package(oc: &OperatingConfig, verbose: i32) {
     if verbose > 0 {
         println!("    Package License ID: {}", &oc.npd_id);
     }
+    let versions = oc.spec_versions();
+    if verbose > 0 {
+        let versions: Vec<String> = versions
+            .iter()
+            .map(|v| format!("{} {}", v.field, &v.version))
+            .collect();
+        println!("    Spec versions: {}", versions.join(", "));
+    }
+    // the license spec version is described with each license
+    let npd_version = versions.iter().find(|v| v.field == "npdSpecVersion");
+    if let Some(warning) = npd_version.and_then(SpecVersion::warning) {
+        println!("    Warning: {}", warning);
+    }
     println!("    License type: {}", &oc.mode);
+    if oc.environment == Environment::Stage {
+        println!("    Warning: this is a stage license, which is only for testing");
+    }
+    if let DeploymentMode::FrlLan(_, Some(fingerprint)) = &oc.mode {
+        println!("    Server certificate fingerprint: {}", fingerprint);
+    }
     if verbose > 0 {
         if let DeploymentMode::FrlIsolated(codes) = &oc.mode {
             if codes.len() == 1 {
@@ -114,11 +1222,45 @@ fn describe_package(oc: &OperatingConfig, verbose: i32) {
                 println!("    Census codes: {}", codes.join(", "));
             }
         }
+        let params = oc.device_params();
+        if !params.is_empty() {
+            println!(
+                "    Device parameters sent to server: {}",
+                params.join(", ")
+            );
+        }
     }
     println!("    License expiry date: {}", &oc.expiry_date);
+    if verbose > 0 {
+        describe_license_terms(oc);
+    }
     println!("    Precedence: {}", &oc.precedence);
 }
 
+fn describe_license_terms(oc: &OperatingConfig) {
+    match oc.license_terms() {
+        Ok(Some(terms)) => {
+            println!("    License ID: {}", &terms.license_id);
+            println!("    Effective end date: {}", &terms.effective_end_date);
+            println!(
+                "    Expiry warnings start: {}",
+                &terms.expiry_warning_start_date
+            );
+            println!("    Grace period: {}", &terms.grace_period);
+            println!(
+                "    Profile status: {}, entitlement status: {}",
+                &terms.profile_status, &terms.app_entitlement_status
+            );
+            println!(
+                "    Refresh intervals: {} (app), {} (licensing library)",
+                &terms.app_refresh_interval, &terms.ngl_lib_refresh_interval
+            );
+        }
+        Ok(None) => {}
+        Err(err) => println!("    License terms: unreadable ({:#})", err),
+    }
+}
+
 fn describe_app(count: i32, app_id: &str, group_id: &str, verbose: i32) {
     println!(
         "{}App ID: {}{}",
@@ -135,3 +1277,223 @@ fn describe_app(count: i32, app_id: &str, group_id: &str, verbose: i32) {
         }
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use structopt::StructOpt;
+
+    fn opt(args: &[&str]) -> Opt {
+        Opt::from_iter(
+            std::iter::once("adobe-license-decoder").chain(args.iter().copied()),
+        )
+    }
+
+    #[test]
+    fn test_decode_tree() {
+        let opt = opt(&["--recursive"]);
+        let groups = decode_tree("rsrc", &opt).unwrap();
+        let paths: Vec<&str> = groups.iter().map(|g| g.path.as_str()).collect();
+        assert_eq!(paths.len(), 11);
+        assert_eq!(paths[0], "rsrc/files");
+        assert_eq!(paths[1], "rsrc/packages/mac/isolated-illustrator");
+        assert_eq!(paths[10], "rsrc/verify/licenses");
+        assert!(groups.iter().all(|g| g.all_errors().next().is_none()));
+        // damaged files are listed with their directory, and the
+        // rest of the tree is still decoded
+        let name = format!("adobe-license-decoder-tree-{}", std::process::id());
+        let root = std::env::temp_dir().join(name);
+        let damaged = root.join("damaged");
+        std::fs::create_dir_all(&damaged).unwrap();
+        std::fs::write(damaged.join("broken.operatingconfig"), "not a license").unwrap();
+        std::fs::write(damaged.join("broken.ccp"), "not a package").unwrap();
+        let package = root.join("package");
+        std::fs::create_dir_all(&package).unwrap();
+        let json = "rsrc/packages/mac/isolated-photoshop/ngl-preconditioning-data.json";
+        std::fs::copy(json, package.join("ngl-preconditioning-data.json")).unwrap();
+        let groups = decode_tree(root.to_str().unwrap(), &opt);
+        let status =
+            describe_tree(&FileInfo::from_path(root.to_str().unwrap()).unwrap(), &opt);
+        std::fs::remove_dir_all(&root).unwrap();
+        let groups = groups.unwrap();
+        assert_eq!(groups.len(), 2);
+        assert!(groups[0].path.ends_with("damaged"));
+        assert!(groups[0].decoded.iter().all(|d| d.ocs.is_empty()));
+        assert_eq!(groups[0].all_errors().count(), 2);
+        assert!(groups[1].path.ends_with("package"));
+        assert_eq!(groups[1].decoded.len(), 1);
+        assert!(groups[1].errors.is_empty());
+        assert_eq!(status.unwrap(), 2);
+    }
+
+    #[test]
+    fn test_decode_tree_with_glob_characters() {
+        // support folders can have any name, including glob syntax
+        let name = format!("adobe-license-decoder-glob-{}", std::process::id());
+        let root = std::env::temp_dir().join(name);
+        let case = root.join("case [12]");
+        std::fs::create_dir_all(&case).unwrap();
+        for entry in std::fs::read_dir("rsrc/verify/licenses").unwrap() {
+            let path = entry.unwrap().path();
+            std::fs::copy(&path, case.join(path.file_name().unwrap())).unwrap();
+        }
+        let opt = opt(&["--recursive", "--certificates"]);
+        let groups = decode_tree(root.to_str().unwrap(), &opt);
+        std::fs::remove_dir_all(&root).unwrap();
+        let groups = groups.unwrap();
+        assert_eq!(groups.len(), 1);
+        assert!(groups[0].path.ends_with("case [12]"));
+        assert!(groups[0].all_errors().next().is_none());
+        assert_eq!(groups[0].decoded.len(), 1);
+        assert_eq!(groups[0].decoded[0].ocs.len(), 1);
+        assert_eq!(groups[0].decoded[0].bundled.certificates.len(), 2);
+    }
+
+    #[test]
+    fn test_mixed_directory() {
+        let name = format!("adobe-license-decoder-mixed-{}", std::process::id());
+        let dir = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        let json = "rsrc/packages/mac/isolated-photoshop/ngl-preconditioning-data.json";
+        std::fs::copy(json, dir.join("ngl-preconditioning-data.json")).unwrap();
+        let license = "SWxsdXN0cmF0b3Ixe30yMDE4MDcyMDA0-OTUzZTViZWYtYWJmMy00NGUxLWFjYjUtZmZhN2MyMDY4YjQx-80.operatingconfig";
+        std::fs::copy(format!("rsrc/files/{}", license), dir.join(license)).unwrap();
+        std::fs::write(dir.join("broken.ccp"), "not a package").unwrap();
+        let path = dir.to_str().unwrap();
+        let opt = opt(&["--format", "json"]);
+        let group = decode_directory(path, &opt);
+        let checked = lint_directory(path);
+        std::fs::remove_dir_all(&dir).unwrap();
+        // every file is decoded, and each report says where it came from
+        let findings = findings_for_group(&group, &opt).unwrap();
+        let report = json_directory(&group, &findings, &opt).unwrap();
+        let report = serde_json::to_value(&report).unwrap();
+        let keys: Vec<&String> = report.as_object().unwrap().keys().collect();
+        assert_eq!(keys, vec!["errors", "path", "reports"]);
+        let reports = report["reports"].as_array().unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0]["source"], "preconditioningData");
+        assert!(reports[0]["path"]
+            .as_str()
+            .unwrap()
+            .ends_with("ngl-preconditioning-data.json"));
+        assert_eq!(reports[1]["source"], "licenseFiles");
+        assert_eq!(reports[1]["path"], path);
+        let errors = report["errors"].as_array().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]["path"].as_str().unwrap().ends_with("broken.ccp"));
+        // an unreadable package doesn't stop the others from being checked
+        let checked = checked.unwrap();
+        assert_eq!(checked.len(), 2);
+        assert!(checked[0].0.ends_with("ngl-preconditioning-data.json"));
+        assert!(checked[0].1.is_empty());
+        assert!(checked[1].0.ends_with("broken.ccp"));
+        assert_eq!(checked[1].1.len(), 1);
+        assert_eq!(checked[1].1[0].location, "broken.ccp");
+        assert!(checked[1].1[0]
+            .message
+            .starts_with("Can't parse preconditioning data in ccp file"));
+    }
+
+    #[test]
+    fn test_json_report_shape() {
+        let opt = opt(&["--format", "json"]);
+        let info = FileInfo::from_path("rsrc/files").unwrap();
+        let decoded = decode_license_files(&info, &opt).unwrap();
+        let findings = findings(&decoded, &opt).unwrap();
+        let report = json_report(&decoded, &findings, &opt).unwrap();
+        let report = serde_json::to_value(&report).unwrap();
+        let keys: Vec<&String> = report.as_object().unwrap().keys().collect();
+        assert_eq!(
+            keys,
+            vec![
+                "conflicts",
+                "effectiveLicenses",
+                "errors",
+                "operatingConfigs",
+                "path",
+                "source"
+            ]
+        );
+        assert_eq!(report["source"], "licenseFiles");
+        let ocs = report["operatingConfigs"].as_array().unwrap();
+        assert_eq!(ocs.len(), 4);
+        assert_eq!(ocs[0]["precedence"], 80);
+        assert_eq!(ocs[0]["mode"]["type"], "frlLan");
+        assert_eq!(ocs[0]["mode"]["server"], "https://test:123");
+        assert!(ocs[0]["mode"]["serverCertFingerprint"].is_string());
+        let isolated = &ocs[3]["mode"];
+        assert_eq!(isolated["type"], "frlIsolated");
+        assert_eq!(isolated["censusCodes"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_asnp_errors() {
+        let opt = opt(&["--format", "json"]);
+        let path = "rsrc/packages/mac/isolated-photoshop/ngl-preconditioning-data.json";
+        let info = FileInfo::from_path(path).unwrap();
+        let mut data: JsonMap = json_from_file(&info).unwrap();
+        let asnps = data.get_mut("asnps").unwrap().as_array_mut().unwrap();
+        asnps[0]["content"] = "not base64!".into();
+        let decoded = decode_preconditioning_json(&info, &data, &opt).unwrap();
+        // an unreadable ASNP file isn't an unreadable license
+        assert!(decoded.errors.is_empty());
+        let findings = findings(&decoded, &opt).unwrap();
+        let report = json_report(&decoded, &findings, &opt).unwrap();
+        let report = serde_json::to_value(&report).unwrap();
+        assert_eq!(report["errors"].as_array().unwrap().len(), 0);
+        assert_eq!(report["asnpErrors"].as_array().unwrap().len(), 1);
+        assert_eq!(report["asnps"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_json_detail_errors() {
+        let opt = opt(&["--format", "json", "-v", "--features"]);
+        let info = FileInfo::from_path("rsrc/files").unwrap();
+        let mut decoded = decode_license_files(&info, &opt).unwrap();
+        let asnp = decoded.ocs[3].payload.asnp_data.as_mut().unwrap();
+        let adobe = asnp.adobe_cert_signed_values.as_mut().unwrap();
+        adobe.values.grace_time = "a month".into();
+        adobe.values.licensed_features = "none".into();
+        let findings = findings(&decoded, &opt).unwrap();
+        let report = json_report(&decoded, &findings, &opt).unwrap();
+        let report = serde_json::to_value(&report).unwrap();
+        let oc = &report["operatingConfigs"][3];
+        // the details that can't be decoded are reported, not dropped
+        assert!(oc.get("licenseTerms").is_none());
+        assert!(oc.get("licenseFeatures").is_none());
+        assert!(oc["customerValues"].is_object());
+        let errors = oc["detailErrors"].as_array().unwrap();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].as_str().unwrap().contains("graceTime"));
+        assert!(errors[1].as_str().unwrap().contains("licensedFeatures"));
+    }
+
+    #[test]
+    fn test_json_signature_headers() {
+        let opt = opt(&["--format", "json", "-v"]);
+        let info = FileInfo::from_path("rsrc/files").unwrap();
+        let mut decoded = decode_license_files(&info, &opt).unwrap();
+        let asnp = decoded.ocs[3].payload.asnp_data.as_mut().unwrap();
+        asnp.customer_cert_headers[1].header = "broken".into();
+        let findings = findings(&decoded, &opt).unwrap();
+        let report = json_report(&decoded, &findings, &opt).unwrap();
+        let report = serde_json::to_value(&report).unwrap();
+        let ocs = report["operatingConfigs"].as_array().unwrap();
+        let headers = &ocs[0]["signatureHeaders"];
+        assert_eq!(headers["license"].as_array().unwrap().len(), 2);
+        assert!(ocs[0].get("detailErrors").is_none());
+        // a broken customer header doesn't hide the license headers
+        let headers = &ocs[3]["signatureHeaders"];
+        let chain = &headers["license"][0]["certificateDetails"];
+        assert_eq!(
+            chain[0]["sha1Hash"],
+            "ACF138FEE5E2CF52298B8319C6B709230235EF36"
+        );
+        assert!(headers["customer"].is_null());
+        let errors = ocs[3]["detailErrors"].as_array().unwrap();
+        assert_eq!(errors.len(), 1);
+        let error = errors[0].as_str().unwrap();
+        assert!(error.starts_with("asnpData.customerCertHeaders[1].header: "));
+    }
+}
@@ -0,0 +1,30 @@
+/*
+Copyright 2020 Adobe
+All Rights Reserved.
+
+NOTICE: Adobe permits you to use, modify, and distribute this file in
+accordance with the terms of the Adobe license agreement accompanying
+it.
+*/
+//! Decoder for Adobe FRL and SDL license files.
+//!
+//! This library decodes installed license files (`.operatingconfig`),
+//! preconditioning files (`ngl-preconditioning-data.json`), and package
+//! description files (`.ccp`) into [`OperatingConfig`] values.  The
+//! `adobe-license-decoder` command-line tool is a thin reporting layer
+//! over this library.
+pub mod analysis;
+pub mod asnp;
+pub mod certificates;
+pub mod lint;
+pub mod models;
+pub mod monitoring;
+pub mod probe;
+pub mod types;
+pub mod utilities;
+pub mod verification;
+
+pub use types::{
+    DeploymentMode, DirectoryLicenses, FileError, OperatingConfig, Precedence,
+};
+pub use utilities::FileInfo;
@@ -0,0 +1,307 @@
+/*
+Copyright 2020 Adobe
+All Rights Reserved.
+
+NOTICE: Adobe permits you to use, modify, and distribute this file in
+accordance with the terms of the Adobe license agreement accompanying
+it.
+*/
+//! Consistency checks for preconditioning data.
+//!
+//! A package is made for a single npdId and deployment mode, and every
+//! license in it should agree with the package and with each other.
+//! Linting a package decodes each of its files separately, so that one
+//! bad file doesn't hide the problems in the others, and reports each
+//! problem found along with where in the package it was found.
+use crate::asnp::PackageAsnps;
+use crate::certificates::CertificateStore;
+use crate::models::{PackageFile, SignatureHeader};
+use crate::types::{npd_spec_version, DeploymentMode, OperatingConfig};
+use crate::utilities::{json_field, JsonMap};
+use serde::Serialize;
+
+/// A consistency problem found in a package.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Violation {
+    /// Where the problem is, as a path of JSON names and array indexes
+    /// (in file order) separated by arrows.
+    pub location: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}", &self.location, &self.message)
+    }
+}
+
+/// Check parsed preconditioning data for consistency, returning the
+/// problems found in the order they appear in the package.
+pub fn lint_preconditioning_json(data: &JsonMap) -> Vec<Violation> {
+    let mut result = Vec::new();
+    let mut violation =
+        |location: String, message: String| result.push(Violation { location, message });
+    let mut top_level = |name: &str| match json_field(data, name).as_str() {
+        Some(value) => Some(value.to_string()),
+        None => {
+            violation(name.to_string(), "missing or not a string".to_string());
+            None
+        }
+    };
+    let npd_id = top_level("npdId");
+    let npd_spec_declared = top_level("npdSpecVersion").is_some();
+    let deployment_mode = top_level("deploymentMode");
+    if npd_spec_declared {
+        if let Some(warning) = npd_spec_version(data).warning() {
+            violation("npdSpecVersion".to_string(), warning);
+        }
+    }
+    let files = json_field(data, "operatingConfigs").clone();
+    let files: Vec<PackageFile> = match serde_json::from_value(files) {
+        Ok(files) => files,
+        Err(err) => {
+            let location = "operatingConfigs".to_string();
+            violation(location, format!("unreadable ({})", err));
+            return result;
+        }
+    };
+    if files.is_empty() {
+        violation("operatingConfigs".to_string(), "no licenses".to_string());
+    }
+    // licenses are checked in file order, so their locations are correct
+    let mut positions = Vec::new();
+    let mut ocs = Vec::new();
+    for (i, file) in files.iter().enumerate() {
+        match OperatingConfig::from_preconditioning_data(file) {
+            Ok(oc) => {
+                positions.push(i);
+                ocs.push(oc);
+            }
+            Err(err) => {
+                violation(format!("operatingConfigs[{}]", i), format!("{:#}", err))
+            }
+        }
+    }
+    let certificates = match CertificateStore::from_preconditioning_json(data) {
+        Ok(certificates) => certificates,
+        Err(err) => {
+            violation("certificates".to_string(), format!("{:#}", err));
+            CertificateStore::default()
+        }
+    };
+    for error in certificates.errors() {
+        let path = format!("certificates → {}", &error.pathname);
+        violation(path, format!("{:#}", error.error));
+    }
+    for (n, (&i, oc)) in positions.iter().zip(&ocs).enumerate() {
+        let at = |path: &str| format!("operatingConfigs[{}] → {}", i, path);
+        let payload = &oc.payload;
+        if let Some(warning) = oc.envelope.spec_version().warning() {
+            violation(at("content → ocSpecVersion"), warning);
+        }
+        if let Some(npd_id) = &npd_id {
+            if &payload.npd_id != npd_id {
+                let message = format!(
+                    "npdId ({}) doesn't match the package npdId ({})",
+                    &payload.npd_id, npd_id
+                );
+                violation(at("content → payload → npdId"), message);
+            }
+        }
+        if let Some(mode) = &deployment_mode {
+            if &payload.deployment_mode != mode {
+                let message = format!(
+                    "deployment mode ({}) doesn't match the package deployment mode ({})",
+                    &payload.deployment_mode, mode
+                );
+                violation(at("content → payload → deploymentMode"), message);
+            }
+        }
+        for mismatch in oc.filename_mismatches() {
+            violation(at("name"), mismatch);
+        }
+        for mismatch in oc.customer_value_mismatches() {
+            let path = "content → payload → asnpData.customerCertSignedValues";
+            violation(at(path), mismatch);
+        }
+        if n > 0 {
+            let (first, first_oc) = (positions[0], &ocs[0]);
+            if oc.expiry_date != first_oc.expiry_date {
+                let message = format!(
+                    "expiry date ({}) differs from that of operatingConfigs[{}] ({})",
+                    &oc.expiry_date, first, &first_oc.expiry_date
+                );
+                violation(at("content → payload → asnpData"), message);
+            }
+            if payload.profile_server_url != first_oc.payload.profile_server_url {
+                let describe =
+                    |url: &Option<String>| url.clone().unwrap_or_else(|| "none".into());
+                let message = format!(
+                    "server URL ({}) differs from that of operatingConfigs[{}] ({})",
+                    describe(&payload.profile_server_url),
+                    first,
+                    describe(&first_oc.payload.profile_server_url)
+                );
+                violation(at("content → payload → profileServerUrl"), message);
+            }
+        }
+        // SDL packages license each app many times over, so
+        // only other kinds of package can have duplicate licenses
+        let earlier = ocs[..n].iter().position(|other| {
+            !matches!(other.mode, DeploymentMode::Sdl) && other.app_id == oc.app_id
+        });
+        if let Some(j) = earlier {
+            let message = format!(
+                "app {} is already licensed by operatingConfigs[{}]",
+                &oc.app_id, positions[j]
+            );
+            violation(at("name"), message);
+        }
+        let headers = [
+            (
+                "content → signatures",
+                oc.envelope.decode_signature_headers(),
+            ),
+            (
+                "content → payload → asnpData.customerCertHeaders",
+                payload.decode_customer_cert_headers(),
+            ),
+        ];
+        for (path, headers) in headers.iter() {
+            match headers {
+                Ok(headers) => {
+                    for (j, header) in headers.iter().enumerate() {
+                        let location = at(&format!("{}[{}]", path, j));
+                        for message in missing_certificates(header, &certificates) {
+                            violation(location.clone(), message);
+                        }
+                    }
+                }
+                Err(err) => violation(at(path), format!("{:#}", err)),
+            }
+        }
+    }
+    match PackageAsnps::from_preconditioning_json(data, &ocs) {
+        Ok(package) => {
+            for error in &package.errors {
+                let location = format!("asnps → {}", &error.pathname);
+                violation(location, format!("{:#}", error.error));
+            }
+            for asnp in &package.asnps {
+                if let Some(warning) = asnp.spec_version_warning() {
+                    let location =
+                        format!("asnps → {} → content → asnpSpecVersion", &asnp.filename);
+                    violation(location, warning);
+                }
+            }
+            for unmatched in package.licenses_without_asnp(&ocs) {
+                let i = match ocs.iter().position(|oc| std::ptr::eq(oc, unmatched)) {
+                    Some(n) => positions[n],
+                    None => continue,
+                };
+                let location = format!(
+                    "operatingConfigs[{}] → content → payload → asnpData.templateId",
+                    i
+                );
+                let message = format!("no ASNP file for app {}", &unmatched.app_id);
+                violation(location, message);
+            }
+        }
+        Err(err) => violation("asnps".to_string(), format!("{:#}", err)),
+    }
+    result
+}
+
+fn missing_certificates(
+    header: &SignatureHeader, certificates: &CertificateStore,
+) -> Vec<String> {
+    header
+        .chain()
+        .iter()
+        .filter(|detail| certificates.get(&detail.download_path).is_none())
+        .map(|detail| {
+            format!(
+                "certificate '{}' ({}) is missing from the package",
+                &detail.subject_name, &detail.download_path
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::preconditioning_json_from_ccp;
+    use crate::utilities::{json_from_file, FileInfo};
+
+    fn package(name: &str) -> JsonMap {
+        let path = format!("rsrc/packages/mac/{}/ngl-preconditioning-data.json", name);
+        json_from_file(&FileInfo::from_path(&path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_sdl_package() {
+        // SDL packages have many licenses for each app
+        let path = "rsrc/packages/mac/sdl-all_apps_k12/sdl-all_apps_k12.ccp";
+        let data = preconditioning_json_from_ccp(&std::fs::read(path).unwrap()).unwrap();
+        let violations = lint_preconditioning_json(&data);
+        assert!(violations.is_empty(), "{:?}", violations);
+    }
+
+    #[test]
+    fn test_consistent_packages() {
+        for name in &[
+            "isolated-illustrator",
+            "isolated-photoshop",
+            "lan-illustrator",
+            "offline-acrobat",
+            "online-default-allapps",
+            "online-fixed_ip-acrobat",
+            "online-proxy-premiere",
+        ] {
+            let violations = lint_preconditioning_json(&package(name));
+            assert!(violations.is_empty(), "{}: {:?}", name, violations);
+        }
+    }
+
+    #[test]
+    fn test_inconsistent_package() {
+        let mut data = package("isolated-photoshop");
+        data.insert("npdId".into(), "another".into());
+        data.remove("deploymentMode");
+        // the same license twice, and a broken one
+        let ocs = data.get_mut("operatingConfigs").unwrap();
+        let ocs = ocs.as_array_mut().unwrap();
+        let copy = ocs[0].clone();
+        ocs.push(copy);
+        ocs.push(serde_json::json!({"name": "x", "extension": "operatingconfig", "content": ""}));
+        // and the same app again under another certificate group
+        let mut copy = ocs[0].clone();
+        let name = "UGhvdG9zaG9wMXt9MjAxODA3MjAwNQ-ZGQzMjhhY2MtZTE2Yy00NTI0LTgzOWItZGRkMDUwNTIzNGU0-80";
+        copy["name"] = name.into();
+        ocs.push(copy);
+        data.insert("certificates".into(), serde_json::json!([]));
+        let violations = lint_preconditioning_json(&data);
+        let locations: Vec<&str> =
+            violations.iter().map(|v| v.location.as_str()).collect();
+        assert_eq!(locations[0], "deploymentMode");
+        assert!(locations[1].starts_with("operatingConfigs[3]"));
+        assert_eq!(
+            locations[2],
+            "operatingConfigs[0] → content → payload → npdId"
+        );
+        assert!(locations.contains(&"operatingConfigs[2] → name"));
+        let duplicate = violations.iter().find(|v| v.message.starts_with("app "));
+        assert_eq!(
+            duplicate.unwrap().to_string(),
+            "operatingConfigs[2] → name: app Photoshop1 is already licensed by operatingConfigs[0]"
+        );
+        assert!(violations.iter().any(|v| v.to_string()
+            == "operatingConfigs[4] → name: app Photoshop1 is already licensed by operatingConfigs[0]"));
+        assert!(violations.iter().any(|v| {
+            v.location == "operatingConfigs[1] → content → signatures[0]"
+                && v.message.ends_with("is missing from the package")
+        }));
+    }
+}
@@ -8,30 +8,58 @@ it.
 */
 mod cli;
 mod descriptions;
-mod types;
-mod utilities;
+mod tables;
 
+use adobe_license_decoder::monitoring::Status;
+use adobe_license_decoder::FileInfo;
 use cli::{Opt, DEFAULT_CONFIG_DIR};
-use descriptions::{describe_directory, describe_file};
+use descriptions::{describe_directory, describe_file, describe_tree};
 use eyre::Result;
 use structopt::StructOpt;
-use utilities::FileInfo;
 
 fn main() -> Result<()> {
     let opt: Opt = Opt::from_args();
-    if let Ok(info) = FileInfo::from_path(&opt.path) {
-        if info.is_directory {
-            describe_directory(&info, opt.verbose)?;
+    if let Err(message) = opt.validate() {
+        let kind = structopt::clap::ErrorKind::ArgumentConflict;
+        structopt::clap::Error::with_description(&message, kind).exit();
+    }
+    // when monitoring, failures are reported as an UNKNOWN status
+    let failure_status = if opt.is_monitoring() {
+        Status::Unknown as i32
+    } else {
+        1
+    };
+    let status = if let Ok(info) = FileInfo::from_path(&opt.path) {
+        let result = if info.is_directory && opt.recursive {
+            describe_tree(&info, &opt)
+        } else if info.is_directory {
+            describe_directory(&info, &opt)
         } else {
-            describe_file(&info, opt.verbose)?;
+            describe_file(&info, &opt)
+        };
+        match result {
+            Ok(status) => status,
+            Err(err) if opt.is_monitoring() => {
+                println!("{} - {}", Status::Unknown, err);
+                failure_status
+            }
+            Err(err) => return Err(err),
         }
     } else {
-        if opt.path.eq_ignore_ascii_case(DEFAULT_CONFIG_DIR) {
-            eprintln!("Error: There are no licenses installed on this computer")
+        let message = if opt.path.eq_ignore_ascii_case(DEFAULT_CONFIG_DIR) {
+            "There are no licenses installed on this computer".to_string()
+        } else {
+            format!("No such directory: {}", &opt.path)
+        };
+        if opt.is_monitoring() {
+            println!("{} - {}", Status::Unknown, message)
         } else {
-            eprintln!("Error: No such directory: {}", &opt.path)
+            eprintln!("Error: {}", message)
         }
-        std::process::exit(1);
+        failure_status
     };
+    if status != 0 {
+        std::process::exit(status);
+    }
     Ok(())
 }
@@ -0,0 +1,779 @@
+/*
+Copyright 2020 Adobe
+All Rights Reserved.
+
+NOTICE: Adobe permits you to use, modify, and distribute this file in
+accordance with the terms of the Adobe license agreement accompanying
+it.
+*/
+//! Typed models of the JSON data found in license files.
+//!
+//! Field names follow the JSON names, converted to snake case.  Values that
+//! are base64-encoded JSON in the file are kept as strings (so that their
+//! signatures can be checked) and have methods that decode them.
+use crate::utilities::{in_layer, json_from_base64, json_from_str, u64decode};
+use eyre::{Result, WrapErr};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// The spec versions of license (operating config) files that the
+/// decoder was written for.
+pub const OC_SPEC_VERSIONS: &[&str] = &["1.1"];
+/// The spec versions of preconditioning data that the decoder was
+/// written for.  Version 1.1 (used by SDL packages) only differs from
+/// 1.0 in leaving out the ASNP files, which are optional in both.
+pub const NPD_SPEC_VERSIONS: &[&str] = &["1.0", "1.1"];
+/// The spec versions of ASNP files that the decoder was written for.
+pub const ASNP_SPEC_VERSIONS: &[&str] = &["1.2"];
+
+/// The spec version declared by a file, which determines how the file
+/// is decoded.  Each format picks the model for a file from its version,
+/// although so far the known versions of each format share a model;
+/// files with a version the decoder doesn't know are decoded with best
+/// effort, with the model for the latest known version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpecVersion {
+    /// The name of the field that declares the version.
+    pub field: &'static str,
+    /// The declared version, which is empty if none was declared.
+    pub version: String,
+    pub known_versions: &'static [&'static str],
+}
+
+impl SpecVersion {
+    pub fn new(
+        field: &'static str, version: &str, known_versions: &'static [&'static str],
+    ) -> SpecVersion {
+        SpecVersion {
+            field,
+            version: version.to_string(),
+            known_versions,
+        }
+    }
+
+    /// Whether the decoder was written for this version.
+    pub fn is_known(&self) -> bool {
+        self.known_versions.contains(&self.version.as_str())
+    }
+
+    /// A warning that the file was decoded with best effort,
+    /// if the decoder doesn't know this version.
+    pub fn warning(&self) -> Option<String> {
+        if self.is_known() {
+            None
+        } else {
+            Some(format!(
+                "{}, so it was decoded with best effort",
+                self.describe()
+            ))
+        }
+    }
+
+    /// Decode a file that declares this version.  If the decoder doesn't
+    /// know the version, any failure is explained by it.
+    pub fn decode<T>(&self, decode: impl FnOnce() -> Result<T>) -> Result<T> {
+        let result = decode();
+        if self.is_known() {
+            result
+        } else {
+            result.wrap_err_with(|| format!("Can't decode: {}", self.describe()))
+        }
+    }
+
+    /// The spec version declared by the JSON text of a file, read without
+    /// decoding the rest of the file, so that it can explain why the rest
+    /// can't be decoded.  This is `None` if the text isn't a JSON object.
+    pub fn declared(
+        json: &str, field: &'static str, known_versions: &'static [&'static str],
+    ) -> Option<SpecVersion> {
+        let value: serde_json::Value = serde_json::from_str(json).ok()?;
+        let version = value.as_object()?.get(field).and_then(|v| v.as_str());
+        Some(SpecVersion::new(
+            field,
+            version.unwrap_or_default(),
+            known_versions,
+        ))
+    }
+
+    /// Decode a file that may have declared a spec version, as `decode` does.
+    pub fn decode_declared<T>(
+        spec: Option<&SpecVersion>, decode: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        match spec {
+            Some(spec) => spec.decode(decode),
+            None => decode(),
+        }
+    }
+
+    fn describe(&self) -> String {
+        let known = self.known_versions.join(", ");
+        if self.version.is_empty() {
+            format!("{} is missing (known versions: {})", self.field, known)
+        } else {
+            format!(
+                "{} {} is not a version this decoder knows (known versions: {})",
+                self.field, &self.version, known
+            )
+        }
+    }
+}
+
+/// The content of a license (operating config) file.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OcEnvelope {
+    #[serde(default)]
+    pub oc_spec_version: String,
+    #[serde(default)]
+    pub signatures: Vec<SignedHeader>,
+    /// The base64-encoded payload.
+    pub payload: String,
+}
+
+impl OcEnvelope {
+    /// The spec version of the license file.
+    pub fn spec_version(&self) -> SpecVersion {
+        SpecVersion::new("ocSpecVersion", &self.oc_spec_version, OC_SPEC_VERSIONS)
+    }
+
+    /// The spec version declared by the JSON text of a license file.
+    pub fn declared_spec_version(json: &str) -> Option<SpecVersion> {
+        SpecVersion::declared(json, "ocSpecVersion", OC_SPEC_VERSIONS)
+    }
+
+    /// Parse the JSON text of a license file with the model for the
+    /// spec version it declares (see [`OcEnvelope::declared_spec_version`]).
+    pub fn from_json(json: &str, spec: Option<&SpecVersion>) -> Result<OcEnvelope> {
+        match spec.map(|spec| spec.version.as_str()) {
+            Some("1.1") => json_from_str(json),
+            // best effort for unknown versions
+            _ => json_from_str(json),
+        }
+    }
+
+    /// Decode the payload of the license.
+    pub fn decode_payload(&self) -> Result<OcPayload> {
+        json_from_base64(&self.payload)
+    }
+
+    /// Decode the headers of the license signatures.
+    pub fn decode_signature_headers(&self) -> Result<Vec<SignatureHeader>> {
+        decode_headers("signatures", &self.signatures)
+    }
+}
+
+fn decode_headers(path: &str, headers: &[SignedHeader]) -> Result<Vec<SignatureHeader>> {
+    let decode = |(i, signed): (usize, &SignedHeader)| {
+        in_layer(&format!("{}[{}].header", path, i), signed.decode_header())
+    };
+    headers.iter().enumerate().map(decode).collect()
+}
+
+/// A signature and the base64-encoded header that describes it.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedHeader {
+    pub header: String,
+    pub signature: String,
+}
+
+impl SignedHeader {
+    /// Decode the header of the signature.
+    pub fn decode_header(&self) -> Result<SignatureHeader> {
+        json_from_base64(&self.header)
+    }
+}
+
+/// How a signature was made, and the certificates needed to check it.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureHeader {
+    pub content_signature_alg: String,
+    pub trusted_cert_fingerprint_alg: Option<String>,
+    pub trusted_cert_fingerprint_index: Option<u32>,
+    #[serde(default)]
+    pub certificate_details: Vec<CertificateDetails>,
+}
+
+impl SignatureHeader {
+    /// The certificates named in the header, starting with the signing
+    /// certificate and followed by its issuers.
+    pub fn chain(&self) -> Vec<&CertificateDetails> {
+        let mut result: Vec<&CertificateDetails> =
+            self.certificate_details.iter().collect();
+        result.sort_by_key(|details| details.sequence);
+        result
+    }
+}
+
+/// A certificate named in a signature header.  The signing certificate
+/// has sequence 1, and each following certificate issued the one before.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateDetails {
+    pub id: String,
+    pub subject_name: String,
+    pub hex_serial_number: String,
+    pub sha1_hash: String,
+    pub sequence: u32,
+    /// The name of the DER file containing the certificate.
+    pub download_path: String,
+}
+
+/// The payload of a license.  Fields that aren't needed to
+/// report on the license are allowed to be missing.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OcPayload {
+    #[serde(default)]
+    pub id: String,
+    pub npd_id: String,
+    pub ngl_app_id: String,
+    pub npd_precedence: u32,
+    /// SDL licenses have no ASNP data.
+    pub asnp_data: Option<AsnpData>,
+    pub profile_server_url: Option<String>,
+    pub profile_server_cert_fingerprint: Option<String>,
+    pub profile_request_payload_params: Option<ProfileRequestPayloadParams>,
+    pub deployment_mode: String,
+    pub branding: Option<Branding>,
+    pub override_device: Option<bool>,
+}
+
+impl OcPayload {
+    /// The Adobe-signed values in the ASNP data, if there are any.
+    pub fn adobe_signed_values(&self) -> Option<&AdobeSignedValues> {
+        self.asnp_data.as_ref()?.adobe_cert_signed_values.as_ref()
+    }
+
+    /// The customer-signed values in the ASNP data, if there are any.
+    pub fn customer_signed_values(&self) -> Option<&CustomerSignedValues> {
+        self.asnp_data
+            .as_ref()?
+            .customer_cert_signed_values
+            .as_ref()
+    }
+
+    /// Decode the headers of the customer signatures in the ASNP data.
+    pub fn decode_customer_cert_headers(&self) -> Result<Vec<SignatureHeader>> {
+        match &self.asnp_data {
+            Some(asnp) => {
+                let headers = &asnp.customer_cert_headers;
+                decode_headers("asnpData.customerCertHeaders", headers)
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+/// The data used to fill in the license's ASNP template.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AsnpData {
+    #[serde(default)]
+    pub template_id: String,
+    #[serde(default)]
+    pub customer_cert_headers: Vec<SignedHeader>,
+    pub adobe_cert_signed_values: Option<AdobeSignedValues>,
+    pub customer_cert_signed_values: Option<CustomerSignedValues>,
+}
+
+/// The license values signed by Adobe.  Only licenses that don't
+/// get their profile from a server have these.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdobeSignedValues {
+    #[serde(default)]
+    pub signatures: AdobeSignatures,
+    pub values: AdobeValues,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AdobeSignatures {
+    pub signature1: String,
+    pub signature2: String,
+}
+
+/// The Adobe-signed license values.  Timestamps and intervals are in epoch
+/// millis and, like all these values, are given as strings.  Only the
+/// expiry timestamp is needed to report on the license, so the other
+/// values are empty if they are missing.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdobeValues {
+    pub license_expiry_timestamp: String,
+    #[serde(default)]
+    pub enigma_data: String,
+    #[serde(default)]
+    pub grace_time: String,
+    #[serde(default)]
+    pub profile_status: String,
+    #[serde(default)]
+    pub effective_end_timestamp: String,
+    #[serde(default)]
+    pub license_expiry_warning_start_timestamp: String,
+    #[serde(default)]
+    pub ngl_lib_refresh_interval: String,
+    #[serde(default)]
+    pub license_id: String,
+    #[serde(default)]
+    pub licensed_features: String,
+    #[serde(default)]
+    pub app_refresh_interval: String,
+    #[serde(default)]
+    pub app_entitlement_status: String,
+}
+
+impl AdobeValues {
+    /// Decode the enigma data, which describes the licensed product.
+    pub fn decode_enigma_data(&self) -> Result<EnigmaData> {
+        in_layer("enigmaData", json_from_escaped_str(&self.enigma_data))
+    }
+
+    /// Decode the list of licensed features.
+    pub fn decode_licensed_features(&self) -> Result<Vec<String>> {
+        in_layer(
+            "licensedFeatures",
+            json_from_escaped_str(&self.licensed_features),
+        )
+    }
+}
+
+/// Parse JSON that has been escaped for inclusion in a JSON string,
+/// which is how JSON values are filled into ASNP templates.
+fn json_from_escaped_str<T: DeserializeOwned>(s: &str) -> Result<T> {
+    let text: String = json_from_str(&format!("\"{}\"", s))?;
+    json_from_str(&text)
+}
+
+/// The description of the licensed product in a license.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnigmaData {
+    pub product_id: i64,
+    pub serial_key: Option<String>,
+    pub clear_serial_key: Option<String>,
+    pub locale: Option<String>,
+    pub associated_locales: Option<String>,
+    pub platform: Option<i64>,
+    pub isk: Option<i64>,
+    pub customer_id: Option<i64>,
+    pub delivery_method: Option<i64>,
+    pub pc: Option<bool>,
+    pub rb: Option<bool>,
+}
+
+impl EnigmaData {
+    /// A copy of this data with all but the last four digits
+    /// of its serial keys masked.
+    pub fn masked(&self) -> EnigmaData {
+        let mask = |key: &Option<String>| key.as_deref().map(mask_serial_key);
+        EnigmaData {
+            serial_key: mask(&self.serial_key),
+            clear_serial_key: mask(&self.clear_serial_key),
+            ..self.clone()
+        }
+    }
+}
+
+fn mask_serial_key(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    let shown = chars.len().saturating_sub(4);
+    let masked = chars.iter().enumerate();
+    masked
+        .map(|(i, c)| if i < shown { '*' } else { *c })
+        .collect()
+}
+
+/// The license values signed by the customer's certificate.  Only
+/// licenses that don't get their profile from a server have these.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomerSignedValues {
+    #[serde(default)]
+    pub signatures: CustomerSignatures,
+    /// The base64-encoded values.
+    pub values: String,
+}
+
+impl CustomerSignedValues {
+    /// Decode the customer-signed values.
+    pub fn decode_values(&self) -> Result<CustomerValues> {
+        json_from_base64(&self.values)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CustomerSignatures {
+    pub customer_signature1: String,
+    pub customer_signature2: String,
+}
+
+/// The customer-signed license values.  Timestamps and intervals
+/// are in epoch millis.  None of them are needed to report on the
+/// license, so they are allowed to be missing.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomerValues {
+    #[serde(default)]
+    pub npd_id: String,
+    #[serde(default)]
+    pub asnp_id: String,
+    pub creation_timestamp: Option<i64>,
+    pub cache_lifetime: Option<i64>,
+    #[serde(default)]
+    pub response_type: String,
+    pub cache_expiry_warning_control: Option<CacheExpiryWarningControl>,
+    #[serde(default)]
+    pub challenge_codes: Vec<String>,
+    /// Any other values, such as device or override settings.
+    #[serde(flatten)]
+    pub other_values: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheExpiryWarningControl {
+    pub warning_start_timestamp: i64,
+    pub warning_interval: i64,
+}
+
+/// The device and app parameters sent to the license server.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileRequestPayloadParams {
+    #[serde(default)]
+    pub device_params: Vec<String>,
+    #[serde(default)]
+    pub app_params: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Branding {
+    pub name: Option<String>,
+}
+
+/// The content of an ASNP file, whose payload is a template for the
+/// license profile that an app gets from its license.  The template
+/// has `{{name}}` placeholders that are filled in with the values that
+/// Adobe signed in the license (or, for licenses that get their
+/// profile from a server, by the server).
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AsnpEnvelope {
+    #[serde(default)]
+    pub asnp_spec_version: String,
+    #[serde(default)]
+    pub signatures: Vec<SignedHeader>,
+    /// The base64-encoded payload template.
+    pub payload: String,
+}
+
+impl AsnpEnvelope {
+    /// The spec version of the ASNP file.
+    pub fn spec_version(&self) -> SpecVersion {
+        SpecVersion::new(
+            "asnpSpecVersion",
+            &self.asnp_spec_version,
+            ASNP_SPEC_VERSIONS,
+        )
+    }
+
+    /// The spec version declared by the JSON text of an ASNP file.
+    pub fn declared_spec_version(json: &str) -> Option<SpecVersion> {
+        SpecVersion::declared(json, "asnpSpecVersion", ASNP_SPEC_VERSIONS)
+    }
+
+    /// Parse the JSON text of an ASNP file with the model for the
+    /// spec version it declares (see [`AsnpEnvelope::declared_spec_version`]).
+    pub fn from_json(json: &str, spec: Option<&SpecVersion>) -> Result<AsnpEnvelope> {
+        match spec.map(|spec| spec.version.as_str()) {
+            Some("1.2") => json_from_str(json),
+            // best effort for unknown versions
+            _ => json_from_str(json),
+        }
+    }
+
+    /// Decode the payload template, filling in its placeholders with the
+    /// given values.  Placeholders without a value are filled with null.
+    pub fn decode_payload(
+        &self, values: &HashMap<String, String>,
+    ) -> Result<AsnpPayload> {
+        let template = u64decode(&self.payload).wrap_err("Illegal payload encoding")?;
+        json_from_str(&fill_template(&template, values))
+    }
+}
+
+/// Replace the `{{name}}` placeholders in a template with the named values.
+/// The values are inserted as they are, since they are already encoded for
+/// the place they go.  Placeholders without a value, and the quotes around
+/// them, are replaced with null.
+fn fill_template(template: &str, values: &HashMap<String, String>) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => break,
+        };
+        let name = &rest[start + 2..end];
+        let (before, after) = (&rest[..start], &rest[end + 2..]);
+        if let Some(value) = values.get(name) {
+            result.push_str(before);
+            result.push_str(value);
+            rest = after;
+        } else {
+            // the placeholder may be quoted, and the quotes may be escaped
+            let quote = ["\\\"", "\""]
+                .iter()
+                .find(|q| before.ends_with(*q) && after.starts_with(*q));
+            let quote_len = quote.map_or(0, |q| q.len());
+            result.push_str(&before[..before.len() - quote_len]);
+            result.push_str("null");
+            rest = &after[quote_len..];
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// The payload of an ASNP file.  The profiles that are JSON strings
+/// have methods that decode them.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AsnpPayload {
+    pub legacy_profile: String,
+    pub relationship_profile: String,
+    pub control_profile: ControlProfile,
+    pub profile_status: Option<String>,
+    pub app_license_mode: Option<String>,
+}
+
+impl AsnpPayload {
+    /// Decode the legacy profile.
+    pub fn decode_legacy_profile(&self) -> Result<LegacyProfile> {
+        in_layer("legacyProfile", json_from_str(&self.legacy_profile))
+    }
+
+    /// Decode the relationship profile.
+    pub fn decode_relationship_profile(&self) -> Result<Vec<RelationshipProfile>> {
+        in_layer(
+            "relationshipProfile",
+            json_from_str(&self.relationship_profile),
+        )
+    }
+}
+
+/// The license details in an ASNP's legacy profile.  Timestamps and
+/// intervals are in epoch millis.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LegacyProfile {
+    pub license_id: Option<String>,
+    pub license_type: Option<i64>,
+    pub license_version: Option<String>,
+    pub effective_end_timestamp: Option<i64>,
+    pub grace_time: Option<i64>,
+}
+
+/// A license relationship in an ASNP's relationship profile.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelationshipProfile {
+    pub license_id: Option<String>,
+    pub license_expiry_timestamp: Option<i64>,
+    pub app_entitlement_status: Option<String>,
+    pub activation_type: Option<String>,
+    pub billing_status: Option<String>,
+}
+
+/// How an app is controlled by its ASNP.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ControlProfile {
+    pub ngl_app_id: String,
+    pub ngl_lib_runtime_mode: Option<String>,
+}
+
+/// A file embedded in preconditioning data, such as a license file.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageFile {
+    pub name: String,
+    pub extension: String,
+    /// The base64-encoded content of the file.
+    pub content: String,
+}
+
+impl PackageFile {
+    /// Decode the content of a license or ASNP file to its JSON text.
+    pub fn decode_text(&self) -> Result<String> {
+        u64decode(&self.content).wrap_err("Illegal payload encoding")
+    }
+
+    /// Decode the content of a binary file, such as a certificate.
+    pub fn decode_content(&self) -> Result<Vec<u8>> {
+        let content = self.content.trim_end_matches('=');
+        base64::decode_config(content, base64::URL_SAFE_NO_PAD)
+            .wrap_err("Illegal file encoding")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::{json_from_file, FileInfo};
+
+    #[test]
+    fn test_decode_license_file() {
+        let path = "rsrc/files/UGhvdG9zaG9wMXt9MjAxODA3MjAwNA-ZGQzMjhhY2MtZTE2Yy00NTI0LTgzOWItZGRkMDUwNTIzNGU0-80.operatingconfig";
+        let info = FileInfo::from_path(path).unwrap();
+        let envelope: OcEnvelope = json_from_file(&info).unwrap();
+        assert_eq!(envelope.oc_spec_version, "1.1");
+        let payload = envelope.decode_payload().unwrap();
+        assert_eq!(payload.ngl_app_id, "Photoshop1");
+        assert_eq!(payload.npd_precedence, 80);
+        assert_eq!(payload.deployment_mode, "FRL_ISOLATED");
+        let adobe = payload.adobe_signed_values().unwrap();
+        assert_eq!(adobe.values.license_expiry_timestamp, "1636009201000");
+        let customer = payload.customer_signed_values().unwrap();
+        let values = customer.decode_values().unwrap();
+        assert_eq!(values.npd_id, payload.npd_id);
+        assert_eq!(values.challenge_codes.len(), 2);
+        let headers = envelope.decode_signature_headers().unwrap();
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers[0].content_signature_alg, "RS512");
+        assert_eq!(headers[0].trusted_cert_fingerprint_index, Some(53));
+        let chain = headers[0].chain();
+        assert_eq!(chain[0].subject_name, "Adobe Content Certificate 41-6");
+        assert_eq!(chain[1].subject_name, "Adobe Intermediate CA 41-54");
+        let headers = payload.decode_customer_cert_headers().unwrap();
+        assert_eq!(headers[1].chain()[0].hex_serial_number, "2685bb9b");
+    }
+
+    #[test]
+    fn test_decode_signature_headers() {
+        // a header without a trusted fingerprint, listing its chain out of order
+        let header = r#"{"contentSignatureAlg":"RS256","certificateDetails":[
+            {"id":"ca-id","subjectName":"Test CA","hexSerialNumber":"0a",
+             "sha1Hash":"CA-HASH","sequence":2,"downloadPath":"ca.der"},
+            {"id":"signer-id","subjectName":"Test Signer","hexSerialNumber":"0b",
+             "sha1Hash":"SIGNER-HASH","sequence":1,"downloadPath":"signer.der"}]}"#;
+        let signed = SignedHeader {
+            header: crate::utilities::u64encode(header).unwrap(),
+            signature: String::new(),
+        };
+        let header = signed.decode_header().unwrap();
+        assert_eq!(header.content_signature_alg, "RS256");
+        assert_eq!(header.trusted_cert_fingerprint_alg, None);
+        assert_eq!(header.trusted_cert_fingerprint_index, None);
+        let chain = header.chain();
+        assert_eq!(chain[0].id, "signer-id");
+        assert_eq!(chain[0].sha1_hash, "SIGNER-HASH");
+        assert_eq!(chain[0].download_path, "signer.der");
+        assert_eq!(chain[1].subject_name, "Test CA");
+        assert_eq!(chain[1].hex_serial_number, "0a");
+        let broken = SignedHeader {
+            header: "not base64!".into(),
+            signature: String::new(),
+        };
+        let err = decode_headers("signatures", &[signed, broken]).unwrap_err();
+        assert!(format!("{:#}", err).starts_with("signatures[1].header: "));
+    }
+
+    #[test]
+    fn test_decode_enigma_data() {
+        let values = AdobeValues {
+            enigma_data: r#"{\"productId\":45,\"serialKey\":\"104545012345537554907209\",\"clearSerialKey\":null}"#.into(),
+            licensed_features: r#"[\"Bridge_Base_4.0\",\"Photoshop_Base_22.0\"]"#.into(),
+            ..Default::default()
+        };
+        let enigma = values.decode_enigma_data().unwrap();
+        assert_eq!(enigma.product_id, 45);
+        assert_eq!(
+            enigma.masked().serial_key.as_deref(),
+            Some("********************7209")
+        );
+        assert_eq!(enigma.masked().clear_serial_key, None);
+        let features = values.decode_licensed_features().unwrap();
+        assert_eq!(features, vec!["Bridge_Base_4.0", "Photoshop_Base_22.0"]);
+    }
+
+    #[test]
+    fn test_spec_version() {
+        let known = SpecVersion::new("ocSpecVersion", "1.1", OC_SPEC_VERSIONS);
+        assert!(known.is_known());
+        assert_eq!(known.warning(), None);
+        let err = known
+            .decode(|| Err::<(), _>(eyre::eyre!("bad")))
+            .unwrap_err();
+        assert_eq!(format!("{:#}", err), "bad");
+        let future = SpecVersion::new("ocSpecVersion", "2.0", OC_SPEC_VERSIONS);
+        assert_eq!(
+            future.warning().unwrap(),
+            "ocSpecVersion 2.0 is not a version this decoder knows (known versions: 1.1), \
+            so it was decoded with best effort"
+        );
+        assert_eq!(future.decode(|| Ok(3)).unwrap(), 3);
+        let err = future
+            .decode(|| Err::<(), _>(eyre::eyre!("bad")))
+            .unwrap_err();
+        assert!(format!("{:#}", err).starts_with("Can't decode: ocSpecVersion 2.0"));
+        let missing = SpecVersion::new("npdSpecVersion", "", NPD_SPEC_VERSIONS);
+        assert!(missing
+            .warning()
+            .unwrap()
+            .starts_with("npdSpecVersion is missing"));
+        let sdl = SpecVersion::new("npdSpecVersion", "1.1", NPD_SPEC_VERSIONS);
+        assert!(sdl.is_known());
+    }
+
+    #[test]
+    fn test_declared_spec_version() {
+        let json = r#"{"ocSpecVersion":"2.0","payload":7}"#;
+        let spec = OcEnvelope::declared_spec_version(json).unwrap();
+        assert_eq!(spec.version, "2.0");
+        // the version explains why the rest of the file can't be decoded
+        let err = SpecVersion::decode_declared(Some(&spec), || {
+            OcEnvelope::from_json(json, Some(&spec))
+        })
+        .unwrap_err();
+        assert!(format!("{:#}", err).starts_with("Can't decode: ocSpecVersion 2.0"));
+        // a missing version is decoded as if it were known
+        let json = r#"{"payload":"e30"}"#;
+        let spec = OcEnvelope::declared_spec_version(json).unwrap();
+        assert_eq!(spec.version, "");
+        let envelope = OcEnvelope::from_json(json, Some(&spec)).unwrap();
+        assert_eq!(envelope.spec_version(), spec);
+        let asnp = AsnpEnvelope::from_json(json, None).unwrap();
+        assert!(asnp
+            .spec_version()
+            .warning()
+            .unwrap()
+            .contains("is missing"));
+        // text that isn't a JSON object declares nothing
+        assert_eq!(OcEnvelope::declared_spec_version("[1.1]"), None);
+        assert_eq!(AsnpEnvelope::declared_spec_version("{oops"), None);
+    }
+
+    #[test]
+    fn test_fill_template() {
+        let template =
+            r#"{"a":"{\"id\":\"{{id}}\",\"end\":{{end}}}","b":"{{id}}","c":{{c}}}"#;
+        let mut values = HashMap::new();
+        values.insert("end".to_string(), "1633417201000".to_string());
+        assert_eq!(
+            fill_template(template, &values),
+            r#"{"a":"{\"id\":null,\"end\":1633417201000}","b":null,"c":null}"#
+        );
+        values.insert("id".to_string(), "96CB".to_string());
+        assert_eq!(
+            fill_template(template, &values),
+            r#"{"a":"{\"id\":\"96CB\",\"end\":1633417201000}","b":"96CB","c":null}"#
+        );
+    }
+}
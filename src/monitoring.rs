@@ -0,0 +1,231 @@
+/*
+Copyright 2020 Adobe
+All Rights Reserved.
+
+NOTICE: Adobe permits you to use, modify, and distribute this file in
+accordance with the terms of the Adobe license agreement accompanying
+it.
+*/
+//! Expiry monitoring for licenses, in the style of a Nagios plugin.
+//!
+//! Each expiry date is checked against warning and critical thresholds,
+//! and the worst status found is the overall status.  Statuses map onto
+//! the Nagios plugin exit codes: 0 for OK, 1 for WARNING, 2 for CRITICAL,
+//! and 3 for UNKNOWN (used when some of the licenses can't be checked).
+//! The one-line summary follows the Nagios output convention of the
+//! status, a dash, and the details.
+use crate::types::OperatingConfig;
+use crate::utilities::datetime_from_epoch_millis;
+use chrono::{DateTime, Local, TimeZone};
+use serde::Serialize;
+
+/// A monitoring status, whose value is the Nagios-style exit code for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Status {
+    Ok = 0,
+    Warning = 1,
+    Critical = 2,
+    Unknown = 3,
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Status::Ok => "OK".fmt(f),
+            Status::Warning => "WARNING".fmt(f),
+            Status::Critical => "CRITICAL".fmt(f),
+            Status::Unknown => "UNKNOWN".fmt(f),
+        }
+    }
+}
+
+/// The thresholds (in days before expiry) at which expiry dates
+/// produce warning and critical statuses.  Expiry dates that have
+/// already passed are always critical.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Thresholds {
+    pub warn_days: Option<i64>,
+    pub critical_days: Option<i64>,
+}
+
+impl Thresholds {
+    pub fn status(&self, days_left: i64) -> Status {
+        if days_left < 0 || matches!(self.critical_days, Some(days) if days_left <= days)
+        {
+            Status::Critical
+        } else if matches!(self.warn_days, Some(days) if days_left <= days) {
+            Status::Warning
+        } else {
+            Status::Ok
+        }
+    }
+}
+
+/// Which expiry date of a license was checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpiryKind {
+    License,
+    CachedActivation,
+}
+
+impl std::fmt::Display for ExpiryKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ExpiryKind::License => "license".fmt(f),
+            ExpiryKind::CachedActivation => "cached activation".fmt(f),
+        }
+    }
+}
+
+/// The result of checking one expiry date of a license.  The
+/// expiry date is in the time zone in which it was checked.
+pub struct ExpiryCheck<'a, Tz: TimeZone = Local> {
+    pub license: &'a OperatingConfig,
+    pub kind: ExpiryKind,
+    pub expiry: DateTime<Tz>,
+    pub days_left: i64,
+    pub status: Status,
+}
+
+/// Check the expiry dates of the given licenses as of `now`.  Licenses whose
+/// expiry is controlled by a server have no expiry date to check.  If
+/// `check_cached` is true, the expiry date of the locally cached activation
+/// of each license (if there is one) is also checked.  Days are counted in
+/// the time zone of `now`.  The result is sorted so that the dates closest
+/// to (or furthest past) expiry come first.
+pub fn check_expiry<'a, Tz: TimeZone>(
+    ocs: &'a [OperatingConfig], now: DateTime<Tz>, thresholds: &Thresholds,
+    check_cached: bool,
+) -> Vec<ExpiryCheck<'a, Tz>> {
+    let mut result = Vec::new();
+    let mut check =
+        |license: &'a OperatingConfig, kind: ExpiryKind, expiry: DateTime<Local>| {
+            let expiry = expiry.with_timezone(&now.timezone());
+            let days_left =
+                (expiry.naive_local().date() - now.naive_local().date()).num_days();
+            result.push(ExpiryCheck {
+                license,
+                kind,
+                expiry,
+                days_left,
+                status: thresholds.status(days_left),
+            })
+        };
+    for oc in ocs {
+        if let Some(expiry) = oc.expiry_time {
+            check(oc, ExpiryKind::License, expiry);
+        }
+        if check_cached {
+            if let Ok(timestamp) = oc.get_cached_expiry() {
+                if let Ok(expiry) = datetime_from_epoch_millis(&timestamp) {
+                    check(oc, ExpiryKind::CachedActivation, expiry);
+                }
+            }
+        }
+    }
+    result.sort_by_key(|check| check.days_left);
+    result
+}
+
+/// The overall status of a set of expiry checks.
+pub fn overall_status<Tz: TimeZone>(checks: &[ExpiryCheck<Tz>]) -> Status {
+    checks
+        .iter()
+        .map(|check| check.status)
+        .max()
+        .unwrap_or(Status::Ok)
+}
+
+/// A one-line summary of a set of expiry checks, suitable for alerting.
+pub fn summary<Tz: TimeZone>(checks: &[ExpiryCheck<Tz>]) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    let status = overall_status(checks);
+    if checks.is_empty() {
+        return format!("{} - no license expiry dates to check", status);
+    }
+    let problems: Vec<String> = checks
+        .iter()
+        .filter(|check| check.status != Status::Ok)
+        .map(|check| {
+            let date = check.expiry.format("%Y-%m-%d");
+            if check.days_left < 0 {
+                format!(
+                    "{} {} expired {} ({} days ago)",
+                    &check.license.app_id, check.kind, date, -check.days_left
+                )
+            } else {
+                format!(
+                    "{} {} expires {} (in {} days)",
+                    &check.license.app_id, check.kind, date, check.days_left
+                )
+            }
+        })
+        .collect();
+    if problems.is_empty() {
+        let first = &checks[0];
+        format!(
+            "{} - {} expiry dates checked, the earliest is {} (in {} days)",
+            status,
+            checks.len(),
+            first.expiry.format("%Y-%m-%d"),
+            first.days_left
+        )
+    } else {
+        format!(
+            "{} - {} of {} expiry dates need attention: {}",
+            status,
+            problems.len(),
+            checks.len(),
+            problems.join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::FileInfo;
+    use chrono::Utc;
+
+    #[test]
+    fn test_thresholds() {
+        let thresholds = Thresholds {
+            warn_days: Some(30),
+            critical_days: Some(7),
+        };
+        assert_eq!(thresholds.status(31), Status::Ok);
+        assert_eq!(thresholds.status(30), Status::Warning);
+        assert_eq!(thresholds.status(7), Status::Critical);
+        assert_eq!(thresholds.status(-1), Status::Critical);
+        assert_eq!(Thresholds::default().status(0), Status::Ok);
+        assert_eq!(Thresholds::default().status(-1), Status::Critical);
+    }
+
+    #[test]
+    fn test_check_expiry() {
+        let info = FileInfo::from_path("rsrc/files").unwrap();
+        let ocs = OperatingConfig::from_directory(&info).unwrap().licenses;
+        let thresholds = Thresholds {
+            warn_days: Some(30),
+            critical_days: None,
+        };
+        // the isolated licenses expire 2021-11-04, the LAN licenses have no expiry
+        let now = Utc.ymd(2021, 10, 15).and_hms(12, 0, 0);
+        let checks = check_expiry(&ocs, now, &thresholds, false);
+        assert_eq!(checks.len(), 2);
+        assert_eq!(checks[0].days_left, 20);
+        assert_eq!(overall_status(&checks), Status::Warning);
+        assert!(summary(&checks).starts_with("WARNING - 2 of 2 expiry dates"));
+        let now = Utc.ymd(2021, 1, 15).and_hms(12, 0, 0);
+        let checks = check_expiry(&ocs, now, &thresholds, false);
+        assert_eq!(overall_status(&checks), Status::Ok);
+        assert!(summary(&checks).starts_with("OK - 2 expiry dates checked"));
+        let now = Utc.ymd(2022, 1, 15).and_hms(12, 0, 0);
+        let checks = check_expiry(&ocs, now, &thresholds, false);
+        assert_eq!(overall_status(&checks), Status::Critical);
+        assert!(summary(&checks).contains("Bridge1 license expired 2021-11-04"));
+    }
+}
@@ -0,0 +1,391 @@
+/*
+Copyright 2020 Adobe
+All Rights Reserved.
+
+NOTICE: Adobe permits you to use, modify, and distribute this file in
+accordance with the terms of the Adobe license agreement accompanying
+it.
+*/
+//! Probing the license servers named in licenses.
+//!
+//! FRL LAN licenses, and FRL Online licenses that go through a proxy,
+//! name the server that apps contact for their license profiles.  LAN
+//! licenses also pin the certificate of that server by its SHA-512
+//! fingerprint.  Probing a server connects to it (completing the TLS
+//! handshake for `https` servers), times the connection, and compares
+//! the certificate the server presents with the pinned fingerprint.
+use crate::certificates::{common_name, hex};
+use crate::types::{DeploymentMode, OperatingConfig};
+use eyre::{eyre, Result, WrapErr};
+use ring::digest::{digest, SHA512};
+use rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, ClientConnection, DigitallySignedStruct, SignatureScheme};
+use serde::Serialize;
+use std::convert::TryFrom;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A license server to probe, and the licenses that name it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeTarget {
+    pub server: String,
+    /// The fingerprint that the licenses pin the server certificate to.
+    pub expected_fingerprint: Option<String>,
+    pub app_ids: Vec<String>,
+}
+
+/// What was found when probing a license server.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeResult {
+    #[serde(flatten)]
+    pub target: ProbeTarget,
+    pub reachable: bool,
+    /// How long it took to connect and (for `https` servers) to
+    /// complete the TLS handshake.
+    pub latency_millis: Option<u64>,
+    pub certificate_subject: Option<String>,
+    /// The SHA-512 fingerprint of the certificate the server presented.
+    pub certificate_fingerprint: Option<String>,
+    /// Whether the presented certificate matches the expected fingerprint,
+    /// if there is a certificate and a fingerprint to compare.
+    pub fingerprint_matches: Option<bool>,
+    /// Why the result can't be trusted, even though the server was probed.
+    pub warning: Option<String>,
+    /// Why the server could not be reached.
+    pub error: Option<String>,
+}
+
+/// The hosts of Adobe's own license servers, which FRL Online
+/// licenses use unless they go through a proxy.
+const ADOBE_SERVER_HOSTS: &[&str] = &["lcs-cops.adobe.io", "lcs-cops-stage.adobe.io"];
+
+/// The servers named by the given licenses, in the order they are first
+/// named.  FRL Online licenses that use Adobe's own server are skipped.
+pub fn probe_targets(ocs: &[OperatingConfig]) -> Vec<ProbeTarget> {
+    let mut result: Vec<ProbeTarget> = Vec::new();
+    for oc in ocs {
+        let (server, fingerprint) = match &oc.mode {
+            DeploymentMode::FrlLan(server, fingerprint) => (server, fingerprint.clone()),
+            DeploymentMode::FrlOnline(server) if !is_adobe_server(server) => {
+                (server, None)
+            }
+            _ => continue,
+        };
+        let existing = result.iter_mut().find(|target| {
+            &target.server == server && target.expected_fingerprint == fingerprint
+        });
+        match existing {
+            Some(target) => target.app_ids.push(oc.app_id.clone()),
+            None => result.push(ProbeTarget {
+                server: server.clone(),
+                expected_fingerprint: fingerprint,
+                app_ids: vec![oc.app_id.clone()],
+            }),
+        }
+    }
+    result
+}
+
+/// Whether a server URL names one of Adobe's own license servers.
+/// Licenses that name no server at all use Adobe's.
+fn is_adobe_server(url: &str) -> bool {
+    match parse_server_url(url) {
+        Ok((_, host, _)) => ADOBE_SERVER_HOSTS
+            .iter()
+            .any(|adobe| host.eq_ignore_ascii_case(adobe)),
+        Err(_) => false,
+    }
+}
+
+impl ProbeTarget {
+    /// Connect to the server, giving up on any step that takes
+    /// longer than `timeout`.
+    pub fn probe(&self, timeout: Duration) -> ProbeResult {
+        let mut result = ProbeResult {
+            target: self.clone(),
+            reachable: false,
+            latency_millis: None,
+            certificate_subject: None,
+            certificate_fingerprint: None,
+            fingerprint_matches: None,
+            warning: None,
+            error: None,
+        };
+        // plain http servers present no certificate to compare
+        if self.expected_fingerprint.is_some() && self.server.starts_with("http://") {
+            result.warning = Some(
+                "the licenses pin a certificate fingerprint, but it can't be checked \
+                because the server uses plain http"
+                    .to_string(),
+            );
+        }
+        match connect(&self.server, timeout) {
+            Ok((elapsed, certificate)) => {
+                result.reachable = true;
+                result.latency_millis = Some(elapsed.as_millis() as u64);
+                if let Some(der) = certificate {
+                    let fingerprint = hex(digest(&SHA512, &der).as_ref());
+                    if let Some(expected) = &self.expected_fingerprint {
+                        result.fingerprint_matches =
+                            Some(normalize(expected) == fingerprint);
+                    }
+                    if let Ok((_, cert)) = x509_parser::parse_x509_certificate(&der) {
+                        result.certificate_subject = Some(common_name(cert.subject()));
+                    }
+                    result.certificate_fingerprint = Some(fingerprint);
+                }
+            }
+            Err(err) => result.error = Some(format!("{:#}", err)),
+        }
+        result
+    }
+}
+
+/// Fingerprints are compared as uppercase hex without separators.
+fn normalize(fingerprint: &str) -> String {
+    fingerprint
+        .chars()
+        .filter(|c| c.is_ascii_hexdigit())
+        .collect::<String>()
+        .to_ascii_uppercase()
+}
+
+/// Connect to a server, returning how long it took and, for `https`
+/// servers, the certificate that the server presented.
+fn connect(server: &str, timeout: Duration) -> Result<(Duration, Option<Vec<u8>>)> {
+    let (tls, host, port) = parse_server_url(server)?;
+    let addresses = (host.as_str(), port)
+        .to_socket_addrs()
+        .wrap_err_with(|| format!("Cannot resolve {}", host))?;
+    let start = Instant::now();
+    let mut last_error = eyre!("No addresses found for {}", host);
+    let mut stream = None;
+    for address in addresses {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(s) => {
+                stream = Some(s);
+                break;
+            }
+            Err(err) => last_error = eyre!(err).wrap_err("Cannot connect"),
+        }
+    }
+    let mut stream = stream.ok_or(last_error)?;
+    if !tls {
+        return Ok((start.elapsed(), None));
+    }
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AnyCertificate(provider)))
+        .with_no_client_auth();
+    let name = ServerName::try_from(host)?;
+    let mut connection = ClientConnection::new(Arc::new(config), name)?;
+    while connection.is_handshaking() {
+        connection
+            .complete_io(&mut stream)
+            .wrap_err("TLS handshake failed")?;
+    }
+    let elapsed = start.elapsed();
+    let certificate = connection
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .map(|cert| cert.as_ref().to_vec());
+    Ok((elapsed, certificate))
+}
+
+/// Split a server URL into whether it uses TLS, its host, and its port.
+fn parse_server_url(url: &str) -> Result<(bool, String, u16)> {
+    let err = || eyre!("Invalid server URL: {}", url);
+    let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+        (true, rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        (false, rest)
+    } else {
+        return Err(err());
+    };
+    let authority = rest.split(&['/', '?', '#'][..]).next().unwrap_or_default();
+    let (host, port) = if let Some(rest) = authority.strip_prefix('[') {
+        // an IPv6 address, with an optional port after the bracket
+        let (host, rest) = rest.split_once(']').ok_or_else(err)?;
+        (host, rest.strip_prefix(':'))
+    } else {
+        match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        }
+    };
+    if host.is_empty() {
+        return Err(err());
+    }
+    let port = match port {
+        Some(port) => port.parse::<u16>().map_err(|_| err())?,
+        None if tls => 443,
+        None => 80,
+    };
+    Ok((tls, host.to_string(), port))
+}
+
+/// Accepts any server certificate, since the point of a probe is to
+/// see which certificate the server presents.  The handshake signatures
+/// are still checked, so the server must hold the certificate's key.
+#[derive(Debug)]
+struct AnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AnyCertificate {
+    fn verify_server_cert(
+        &self, _end_entity: &CertificateDer<'_>, _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>, _ocsp_response: &[u8], _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        let algorithms = &self.0.signature_verification_algorithms;
+        verify_tls12_signature(message, cert, dss, algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        let algorithms = &self.0.signature_verification_algorithms;
+        verify_tls13_signature(message, cert, dss, algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::FileInfo;
+    use rustls::pki_types::PrivateKeyDer;
+    use rustls::{ServerConfig, ServerConnection};
+    use std::net::TcpListener;
+
+    // The stand-in server uses the self-signed certificate in rsrc/probe.
+    const CERTIFICATE: &str = "rsrc/probe/server.der";
+    const KEY: &str = "rsrc/probe/server-key.der";
+    const FINGERPRINT: &str = "846b9d32103d1876507f74564647966ccf4ab7a645685cdb071469cbc492366d\
+                               5cf2b79997b594bc1dc917818799adfc9ec9156402db0d7d8ad57cfb373e22c8";
+
+    /// Start a local HTTPS server that accepts a single connection,
+    /// returning its URL.
+    fn start_server() -> String {
+        let certificate = std::fs::read(CERTIFICATE).unwrap();
+        let key = std::fs::read(KEY).unwrap();
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![CertificateDer::from(certificate)],
+                PrivateKeyDer::try_from(key).unwrap(),
+            )
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut connection = ServerConnection::new(Arc::new(config)).unwrap();
+            while connection.is_handshaking() {
+                if connection.complete_io(&mut stream).is_err() {
+                    break;
+                }
+            }
+        });
+        format!("https://localhost:{}", port)
+    }
+
+    fn target(server: &str, expected_fingerprint: Option<&str>) -> ProbeTarget {
+        ProbeTarget {
+            server: server.to_string(),
+            expected_fingerprint: expected_fingerprint.map(String::from),
+            app_ids: vec!["Illustrator1".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_probe_server() {
+        let timeout = Duration::from_secs(10);
+        let server = start_server();
+        let result = target(&server, Some(FINGERPRINT)).probe(timeout);
+        assert!(result.reachable, "{:?}", result.error);
+        assert!(result.latency_millis.is_some());
+        assert_eq!(result.certificate_subject.as_deref(), Some("localhost"));
+        assert_eq!(result.fingerprint_matches, Some(true));
+        // a server whose certificate isn't the pinned one
+        let server = start_server();
+        let result = target(&server, Some("0410EC06")).probe(timeout);
+        assert!(result.reachable);
+        assert_eq!(result.fingerprint_matches, Some(false));
+        assert_eq!(result.certificate_fingerprint, Some(normalize(FINGERPRINT)));
+        // a server that isn't there
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+        let server = format!("https://127.0.0.1:{}", port);
+        let result = target(&server, None).probe(timeout);
+        assert!(!result.reachable);
+        assert!(result.warning.is_none());
+        assert!(result.error.unwrap().starts_with("Cannot connect"));
+        // a pinned fingerprint can't be checked over plain http
+        let server = format!("http://127.0.0.1:{}", port);
+        let result = target(&server, Some(FINGERPRINT)).probe(timeout);
+        assert_eq!(result.fingerprint_matches, None);
+        assert!(result.warning.unwrap().contains("plain http"));
+    }
+
+    #[test]
+    fn test_probe_targets() {
+        let info = FileInfo::from_path("rsrc/files").unwrap();
+        let ocs = OperatingConfig::from_directory(&info).unwrap().licenses;
+        let targets = probe_targets(&ocs);
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].server, "https://test:123");
+        assert_eq!(targets[0].app_ids, vec!["Bridge1", "Illustrator1"]);
+        assert!(targets[0].expected_fingerprint.is_some());
+        // licenses that name Adobe's own server aren't probed
+        let package = |path: &str| {
+            let info = FileInfo::from_path(&format!("rsrc/packages/{}", path)).unwrap();
+            if info.extension == "ccp" {
+                OperatingConfig::from_ccp_file(&info).unwrap()
+            } else {
+                OperatingConfig::from_preconditioning_file(&info).unwrap()
+            }
+        };
+        let online = package("mac/online-default-allapps/ngl-preconditioning-data.json");
+        assert!(!online.is_empty());
+        assert!(probe_targets(&online).is_empty());
+        let online = package("win/online-illustrator/online-illustrator.ccp");
+        assert!(!online.is_empty());
+        assert!(probe_targets(&online).is_empty());
+        // but those that go through a proxy are
+        let proxied = package("mac/online-proxy-premiere/ngl-preconditioning-data.json");
+        assert_eq!(probe_targets(&proxied).len(), 1);
+        assert_eq!(
+            parse_server_url("https://[::1]:8443/path").unwrap(),
+            (true, "::1".to_string(), 8443)
+        );
+        assert_eq!(
+            parse_server_url("http://proxy.example.com").unwrap(),
+            (false, "proxy.example.com".to_string(), 80)
+        );
+        assert!(parse_server_url("ftp://server").is_err());
+        assert!(parse_server_url("https://server:port").is_err());
+    }
+}
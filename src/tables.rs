@@ -0,0 +1,181 @@
+/*
+Copyright 2020 Adobe
+All Rights Reserved.
+
+NOTICE: Adobe permits you to use, modify, and distribute this file in
+accordance with the terms of the Adobe license agreement accompanying
+it.
+*/
+use crate::cli::{Column, Format};
+use adobe_license_decoder::{DeploymentMode, OperatingConfig};
+use eyre::Result;
+use std::io::Write;
+
+/// Print one row per license, in either csv or aligned table format.
+pub fn print_rows(
+    ocs: &[OperatingConfig], columns: &[Column], format: Format,
+) -> Result<()> {
+    write_rows(std::io::stdout(), ocs, columns, format)
+}
+
+fn write_rows(
+    out: impl Write, ocs: &[OperatingConfig], columns: &[Column], format: Format,
+) -> Result<()> {
+    let columns = selected_columns(columns);
+    let header: Vec<String> = columns.iter().map(|c| c.name().to_string()).collect();
+    let rows: Vec<Vec<String>> = ocs
+        .iter()
+        .map(|oc| columns.iter().map(|c| column_value(oc, *c)).collect())
+        .collect();
+    write_table(out, &header, &rows, format)
+}
+
+/// Print one row per license, as with [`print_rows`], for groups of licenses
+/// that came from different places.  Each row starts with the place its
+/// license came from, in a column with the given name.
+pub fn print_grouped_rows(
+    name: &str, groups: &[(&str, &[OperatingConfig])], columns: &[Column], format: Format,
+) -> Result<()> {
+    let columns = selected_columns(columns);
+    let mut header = vec![name.to_string()];
+    header.extend(columns.iter().map(|c| c.name().to_string()));
+    let mut rows = Vec::new();
+    for (place, ocs) in groups {
+        for oc in ocs.iter() {
+            let mut row = vec![place.to_string()];
+            row.extend(columns.iter().map(|c| column_value(oc, *c)));
+            rows.push(row);
+        }
+    }
+    write_table(std::io::stdout(), &header, &rows, format)
+}
+
+fn selected_columns(columns: &[Column]) -> &[Column] {
+    if columns.is_empty() {
+        &Column::ALL[..]
+    } else {
+        columns
+    }
+}
+
+fn write_table(
+    mut out: impl Write, header: &[String], rows: &[Vec<String>], format: Format,
+) -> Result<()> {
+    if format == Format::Csv {
+        let mut writer = csv::Writer::from_writer(out);
+        writer.write_record(header)?;
+        for row in rows {
+            writer.write_record(row)?;
+        }
+        writer.flush()?;
+    } else {
+        // widths are in characters, so that non-ASCII values line up
+        let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+        for row in rows {
+            for (width, value) in widths.iter_mut().zip(row) {
+                *width = (*width).max(value.chars().count());
+            }
+        }
+        write_aligned(&mut out, header, &widths)?;
+        for row in rows {
+            write_aligned(&mut out, row, &widths)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_aligned(
+    out: &mut impl Write, values: &[String], widths: &[usize],
+) -> Result<()> {
+    let cells: Vec<String> = values
+        .iter()
+        .zip(widths)
+        .map(|(value, width)| {
+            let padding = width.saturating_sub(value.chars().count());
+            format!("{}{}", value, " ".repeat(padding))
+        })
+        .collect();
+    writeln!(out, "{}", cells.join("  ").trim_end())?;
+    Ok(())
+}
+
+fn column_value(oc: &OperatingConfig, column: Column) -> String {
+    match column {
+        Column::Filename => oc.filename.clone(),
+        Column::NpdId => oc.npd_id.clone(),
+        Column::AppId => oc.app_id.clone(),
+        Column::CertGroupId => oc.cert_group_id.clone(),
+        Column::Mode => match &oc.mode {
+            DeploymentMode::FrlOnline(_) => "FRL Online".to_string(),
+            DeploymentMode::FrlOffline => "FRL Offline".to_string(),
+            DeploymentMode::FrlIsolated(_) => "FRL Isolated".to_string(),
+            DeploymentMode::FrlLan(..) => "FRL LAN".to_string(),
+            DeploymentMode::Sdl => "SDL".to_string(),
+            DeploymentMode::Unknown(s) => s.clone(),
+        },
+        Column::Server => match &oc.mode {
+            DeploymentMode::FrlOnline(server) | DeploymentMode::FrlLan(server, _) => {
+                server.clone()
+            }
+            _ => String::new(),
+        },
+        Column::CensusCodes => match &oc.mode {
+            DeploymentMode::FrlIsolated(codes) => codes.join(" "),
+            _ => String::new(),
+        },
+        Column::Precedence => (oc.precedence as u32).to_string(),
+        Column::ExpiryDate => oc.expiry_date.clone(),
+        Column::InstallDate => oc.install_datetime.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use adobe_license_decoder::FileInfo;
+
+    fn render(columns: &[Column], format: Format) -> Vec<String> {
+        let info = FileInfo::from_path("rsrc/files").unwrap();
+        let ocs = OperatingConfig::from_directory(&info).unwrap().licenses;
+        let mut out = Vec::new();
+        write_rows(&mut out, &ocs, columns, format).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        text.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_csv_rows() {
+        let columns = [Column::AppId, Column::Mode, Column::Precedence];
+        let lines = render(&columns, Format::Csv);
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "appId,mode,precedence");
+        assert_eq!(lines[1], "Bridge1,FRL LAN,80");
+        let lines = render(&[], Format::Csv);
+        assert!(lines[0].starts_with("filename,npdId,appId,"));
+        assert!(lines[0].ends_with(",expiryDate,installDate"));
+    }
+
+    #[test]
+    fn test_table_rows() {
+        let columns = [Column::AppId, Column::Mode, Column::ExpiryDate];
+        let lines = render(&columns, Format::Table);
+        assert_eq!(lines[0], "appId         mode          expiryDate");
+        assert_eq!(lines[4], "Photoshop1    FRL Isolated  2021-11-04");
+    }
+
+    #[test]
+    fn test_table_with_non_ascii_values() {
+        let header = vec!["name".to_string(), "mode".to_string()];
+        let rows = vec![
+            vec!["Ünïcödé".to_string(), "FRL Online".to_string()],
+            vec!["ascii".to_string(), "SDL".to_string()],
+        ];
+        let mut out = Vec::new();
+        write_table(&mut out, &header, &rows, Format::Table).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "name     mode");
+        assert_eq!(lines[1], "Ünïcödé  FRL Online");
+        assert_eq!(lines[2], "ascii    SDL");
+    }
+}
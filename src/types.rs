@@ -7,12 +7,29 @@ accordance with the terms of the Adobe license agreement accompanying
 it.
 */
 use self::DeploymentMode::*;
+use self::Environment::*;
 use self::Precedence::*;
+use crate::models::{
+    CustomerValues, EnigmaData, OcEnvelope, OcPayload, PackageFile, SpecVersion,
+    NPD_SPEC_VERSIONS,
+};
 use crate::utilities::*;
+use chrono::{DateTime, Local};
 use eyre::{eyre, Result, WrapErr};
+use serde::ser::{SerializeMap, Serializer};
+use serde::Serialize;
+use std::cell::Cell;
+use std::cmp::Ordering::Equal;
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
 use std::io::Read;
+use std::panic::UnwindSafe;
 use std::str::from_utf8;
+use std::sync::Once;
 
+/// The decoded content of a single license (operating config) file.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct OperatingConfig {
     pub filename: String,
     pub app_id: String,
@@ -20,20 +37,41 @@ pub struct OperatingConfig {
     pub npd_id: String,
     pub package_id: String,
     pub precedence: Precedence,
+    pub environment: Environment,
     pub mode: DeploymentMode,
     pub expiry_date: String,
+    #[serde(skip)]
+    pub expiry_time: Option<DateTime<Local>>,
     pub install_datetime: String,
+    #[serde(skip)]
+    pub install_time: Option<DateTime<Local>>,
+    /// The spec version declared by the license file.
+    pub oc_spec_version: String,
+    /// The spec version declared by the preconditioning data that
+    /// the license came from, if it came from preconditioning data.
+    pub npd_spec_version: Option<String>,
+    /// The content of the license file.
+    #[serde(skip)]
+    pub envelope: OcEnvelope,
+    /// The decoded payload of the license file.
+    #[serde(skip)]
+    pub payload: OcPayload,
+    /// The decoded customer-signed values of the license, if it has any.
+    #[serde(skip)]
+    pub customer_values: Option<CustomerValues>,
 }
 
 impl OperatingConfig {
     fn from_file_info(info: &FileInfo) -> Result<OperatingConfig> {
         let err = || eyre!("Invalid license file name: {}", info.name);
         let name_parts: Vec<&str> = info.name.split('-').collect();
-        if name_parts.len() < 3 {
+        if name_parts.len() < 3 || name_parts.len() > 4 {
             return Err(eyre!("Invalid license file name format: {}", info.name));
         }
         let npd_id = name_parts[1].to_string();
         let precedence = Precedence::from(name_parts[2]).wrap_err_with(err)?;
+        let environment = Environment::from(name_parts.get(3).copied());
+        let environment = environment.wrap_err_with(err)?;
         let app_part = u64decode(name_parts[0]).wrap_err_with(err)?;
         let package_id = u64decode(&npd_id).wrap_err_with(err)?;
         let app_info: Vec<&str> = app_part.split("{}").collect();
@@ -49,179 +87,609 @@ impl OperatingConfig {
             npd_id,
             package_id,
             precedence,
+            environment,
             mode: Unknown(String::from("Unknown")),
             expiry_date: String::from("Unknown"),
+            expiry_time: None,
             install_datetime: info.mod_date.to_string(),
+            install_time: info.mod_time,
+            oc_spec_version: String::new(),
+            npd_spec_version: None,
+            envelope: OcEnvelope::default(),
+            payload: OcPayload::default(),
+            customer_values: None,
         })
     }
 
-    fn from_preconditioning_data(data: &JsonMap) -> Result<OperatingConfig> {
-        let err = || eyre!("Invalid preconditioning data: bad license file name");
-        let info = FileInfo::from_name_and_extension(
-            data["name"].as_str().ok_or_else(err)?,
-            data["extension"].as_str().ok_or_else(err)?,
-        )?;
-        let err = || eyre!("Invalid preconditioning data: bad license content");
+    pub(crate) fn from_preconditioning_data(
+        data: &PackageFile,
+    ) -> Result<OperatingConfig> {
+        let info = FileInfo::from_name_and_extension(&data.name, &data.extension)?;
         let mut result = OperatingConfig::from_file_info(&info)?;
-        let content = data["content"].as_str().ok_or_else(err)?;
-        let content = json_from_base64(content).wrap_err_with(err)?;
-        result.update_from_license_data(&content)?;
+        in_layer("content", data.decode_text())
+            .and_then(|json| {
+                let spec = OcEnvelope::declared_spec_version(&json);
+                SpecVersion::decode_declared(spec.as_ref(), || {
+                    let envelope = OcEnvelope::from_json(&json, spec.as_ref());
+                    let envelope = in_layer("content", envelope)?;
+                    in_layer("content", result.update_from_license_data(envelope))
+                })
+            })
+            .wrap_err_with(|| format!("Invalid license data for {}", info.filename))?;
         Ok(result)
     }
 
+    /// Decode an installed license file.
     pub fn from_license_file(info: &FileInfo) -> Result<OperatingConfig> {
+        let data = read_to_string(&info.pathname).wrap_err("Can't read license file")?;
+        OperatingConfig::from_license_data(info, &data)
+    }
+
+    /// Decode the content of a license file that has already been read.
+    /// The name of the file is taken from `info`.
+    pub fn from_license_data(info: &FileInfo, data: &str) -> Result<OperatingConfig> {
         let mut result = OperatingConfig::from_file_info(info)?;
-        let data = json_from_file(info)?;
-        result.update_from_license_data(&data)?;
+        let spec = OcEnvelope::declared_spec_version(data);
+        SpecVersion::decode_declared(spec.as_ref(), || {
+            result.update_from_license_data(OcEnvelope::from_json(data, spec.as_ref())?)
+        })?;
         Ok(result)
     }
 
-    fn update_from_license_data(&mut self, data: &JsonMap) -> Result<()> {
-        let err = || eyre!("License data is invalid: {}", self.filename);
-        let payload = data["payload"].as_str().ok_or_else(err)?;
-        let payload = json_from_base64(payload)?;
-        let mode_string = payload["deploymentMode"].as_str().ok_or_else(err)?;
-        self.mode = match mode_string {
-            "NAMED_USER_EDUCATION_LAB" => Sdl,
-            "FRL_CONNECTED" => {
-                let server = payload["profileServerUrl"]
-                    .as_str()
-                    .unwrap_or("http://lcs-cops.adobe.io")
-                    .to_string();
-                FrlOnline(server)
-            }
-            "FRL_LAN" => {
-                let server = payload["profileServerUrl"]
-                    .as_str()
-                    .ok_or_else(err)?
-                    .to_string();
-                FrlLan(server)
+    fn update_from_license_data(&mut self, envelope: OcEnvelope) -> Result<()> {
+        self.oc_spec_version = envelope.oc_spec_version.clone();
+        let payload = in_layer("payload", envelope.decode_payload())?;
+        let customer_values = match payload.customer_signed_values() {
+            Some(signed) => {
+                let path = "asnpData.customerCertSignedValues.values";
+                Some(in_layer("payload", in_layer(path, signed.decode_values()))?)
             }
-            "FRL_ISOLATED" => {
-                let values = payload["asnpData"]["customerCertSignedValues"]["values"]
-                    .as_str()
-                    .ok_or_else(err)?;
-                let values = json_from_base64(values)?;
-                let codes: Vec<String> =
-                    serde_json::from_value(values["challengeCodes"].clone())?;
-                let code0 = codes.get(0).ok_or_else(err)?;
-                if code0.len() > 18 {
-                    FrlOffline
-                } else {
-                    let codes = codes
-                        .iter()
-                        .map(|code| {
-                            if code.len() != 18 {
-                                "invalid-census-code".to_string()
-                            } else {
-                                format!(
-                                    "{}-{}-{}",
-                                    &code[0..6],
-                                    &code[6..12],
-                                    &code[12..18]
-                                )
-                            }
-                        })
-                        .collect();
-                    FrlIsolated(codes)
-                }
-            }
-            s => Unknown(s.to_string()),
+            None => None,
         };
-        if let Some(expiry_timestamp) = payload["asnpData"]["adobeCertSignedValues"]
-            ["values"]["licenseExpiryTimestamp"]
-            .as_str()
-        {
-            self.expiry_date = date_from_epoch_millis(expiry_timestamp)?;
+        let mode = DeploymentMode::from_payload(&payload, customer_values.as_ref());
+        self.mode = in_layer("payload", mode)?;
+        // stage licenses aren't always named as such, but they
+        // always get their profiles from a stage server
+        if let Some(url) = &payload.profile_server_url {
+            if Environment::from_server_url(url) == Stage {
+                self.environment = Stage;
+            }
+        }
+        if let Some(adobe) = payload.adobe_signed_values() {
+            let timestamp = &adobe.values.license_expiry_timestamp;
+            let path = "asnpData.adobeCertSignedValues.values.licenseExpiryTimestamp";
+            let expiry_time = in_layer(path, datetime_from_epoch_millis(timestamp));
+            let expiry_time = in_layer("payload", expiry_time)?;
+            self.expiry_date = expiry_time.format("%Y-%m-%d").to_string();
+            self.expiry_time = Some(expiry_time);
         } else {
             self.expiry_date = "controlled by server".to_string();
         }
+        self.envelope = envelope;
+        self.payload = payload;
+        self.customer_values = customer_values;
         Ok(())
     }
 
-    fn from_preconditioning_json(data: &JsonMap) -> Result<Vec<OperatingConfig>> {
-        let oc_vec: Vec<JsonMap> =
-            serde_json::from_value(data["operatingConfigs"].clone())?;
-        let mut result: Vec<OperatingConfig> = Vec::new();
-        for oc_data in oc_vec {
-            result.push(OperatingConfig::from_preconditioning_data(&oc_data)?)
+    /// Decode all the license files found in a directory.  Files that
+    /// can't be decoded don't stop the others from being decoded; they
+    /// are returned along with their errors.
+    pub fn from_directory(info: &FileInfo) -> Result<DirectoryLicenses> {
+        let pattern = format!(
+            "{}/*.operatingconfig",
+            glob::Pattern::escape(&info.pathname)
+        );
+        let mut result = DirectoryLicenses::default();
+        for path in glob::glob(&pattern)? {
+            let pathname = path?.to_string_lossy().to_string();
+            let decoded = FileInfo::from_path(&pathname)
+                .and_then(|info| OperatingConfig::from_license_file(&info));
+            match decoded {
+                Ok(oc) => result.licenses.push(oc),
+                Err(error) => result.errors.push(FileError { pathname, error }),
+            }
+        }
+        result
+            .licenses
+            .sort_by(|oc1, oc2| match oc1.npd_id.cmp(&oc2.npd_id) {
+                Equal => oc1.app_id.cmp(&oc2.app_id),
+                otherwise => otherwise,
+            });
+        Ok(result)
+    }
+
+    /// Decode the license data in parsed preconditioning data,
+    /// sorted by app ID.
+    pub fn from_preconditioning_json(data: &JsonMap) -> Result<Vec<OperatingConfig>> {
+        let spec = npd_spec_version(data);
+        let mut result: Vec<OperatingConfig> = spec.decode(|| {
+            package_license_files(data, &spec)?
+                .iter()
+                .map(OperatingConfig::from_preconditioning_data)
+                .collect()
+        })?;
+        for oc in result.iter_mut() {
+            oc.npd_spec_version = Some(spec.version.clone());
         }
         result.sort_by(|oc1, oc2| oc1.app_id.cmp(&oc2.app_id));
         Ok(result)
     }
 
+    /// Decode the license data in a preconditioning (json) file.
     pub fn from_preconditioning_file(info: &FileInfo) -> Result<Vec<OperatingConfig>> {
         let data = json_from_file(info)?;
         OperatingConfig::from_preconditioning_json(&data)
     }
 
+    /// Decode the license data in a package description (ccp) file.
     pub fn from_ccp_file(info: &FileInfo) -> Result<Vec<OperatingConfig>> {
         let bytes = std::fs::read(&info.pathname).wrap_err("Cannot read ccp file")?;
-        // on Windows, this may be a zip file, and we need to extract
-        // the PkgConfig.xml file from it
-        let reader = std::io::Cursor::new(&bytes);
-        let html = if let Ok(mut archive) = zip::ZipArchive::new(reader) {
-            let mut file = archive
-                .by_name("PkgConfig.xml")
-                .map_err(|e| eyre!(e))
-                .wrap_err("Can't find configuration data in ccp archive")?;
-            let mut buffer = String::new();
-            file.read_to_string(&mut buffer)
-                .wrap_err("Can't read configuration data from ccp archive")?;
-            buffer
-        } else {
-            from_utf8(&bytes)
-                .wrap_err("Invalid ccp file format")?
-                .to_string()
-        };
-        let doc = visdom::Vis::load(&html)
-            .map_err(|e| eyre!("{}", e))
-            .wrap_err("Cannot parse ccp file")?;
-        let data_node = doc.find("Preconditioning");
-        let data = data_node.text();
-        let data: JsonMap = serde_json::from_str(data)
-            .wrap_err("Can't parse preconditioning data in ccp file")?;
+        OperatingConfig::from_ccp_data(&bytes)
+    }
+
+    /// Decode the license data in the content of a package description
+    /// (ccp) file.
+    pub fn from_ccp_data(bytes: &[u8]) -> Result<Vec<OperatingConfig>> {
+        let data = preconditioning_json_from_ccp(bytes)?;
         OperatingConfig::from_preconditioning_json(&data)
     }
 
+    /// The license terms that Adobe signed in this license, with their
+    /// dates and durations in readable form.  Licenses that get their
+    /// profile from a server have no signed terms.
+    pub fn license_terms(&self) -> Result<Option<LicenseTerms>> {
+        let values = match self.payload.adobe_signed_values() {
+            Some(adobe) => &adobe.values,
+            None => return Ok(None),
+        };
+        let layer = |name: &str, value: &str, convert: fn(&str) -> Result<String>| {
+            let path = format!("asnpData.adobeCertSignedValues.values.{}", name);
+            let result = present(value).and_then(convert);
+            in_layer("payload", in_layer(&path, result))
+        };
+        Ok(Some(LicenseTerms {
+            license_id: values.license_id.clone(),
+            profile_status: values.profile_status.clone(),
+            app_entitlement_status: values.app_entitlement_status.clone(),
+            effective_end_date: layer(
+                "effectiveEndTimestamp",
+                &values.effective_end_timestamp,
+                date_from_epoch_millis,
+            )?,
+            expiry_warning_start_date: layer(
+                "licenseExpiryWarningStartTimestamp",
+                &values.license_expiry_warning_start_timestamp,
+                date_from_epoch_millis,
+            )?,
+            grace_period: layer("graceTime", &values.grace_time, duration_from_millis)?,
+            app_refresh_interval: layer(
+                "appRefreshInterval",
+                &values.app_refresh_interval,
+                duration_from_millis,
+            )?,
+            ngl_lib_refresh_interval: layer(
+                "nglLibRefreshInterval",
+                &values.ngl_lib_refresh_interval,
+                duration_from_millis,
+            )?,
+        }))
+    }
+
+    /// What this license grants, from the values Adobe signed in it.
+    /// Licenses that get their profile from a server have no signed values.
+    pub fn license_features(&self) -> Result<Option<LicenseFeatures>> {
+        let values = match self.payload.adobe_signed_values() {
+            Some(adobe) => &adobe.values,
+            None => return Ok(None),
+        };
+        let path = "asnpData.adobeCertSignedValues.values";
+        let enigma_data = in_layer(path, values.decode_enigma_data());
+        let licensed_features = in_layer(path, values.decode_licensed_features());
+        Ok(Some(LicenseFeatures {
+            enigma_data: in_layer("payload", enigma_data)?,
+            licensed_features: in_layer("payload", licensed_features)?,
+        }))
+    }
+
+    /// The values the customer signed in this license, with their dates
+    /// and durations in readable form.  Only licenses that are activated
+    /// without a license server have customer-signed values.
+    pub fn customer_terms(&self) -> Result<Option<CustomerTerms>> {
+        let values = match &self.customer_values {
+            Some(values) => values,
+            None => return Ok(None),
+        };
+        let layer = |name: &str, result: Result<String>| {
+            let path = format!("asnpData.customerCertSignedValues.values.{}", name);
+            in_layer("payload", in_layer(&path, result))
+        };
+        let warning = values.cache_expiry_warning_control.as_ref();
+        let (warning_start_date, warning_interval) = match warning {
+            Some(control) => (
+                Some(layer(
+                    "cacheExpiryWarningControl.warningStartTimestamp",
+                    date_from_epoch_millis(&control.warning_start_timestamp.to_string()),
+                )?),
+                Some(layer(
+                    "cacheExpiryWarningControl.warningInterval",
+                    duration_from_millis(&control.warning_interval.to_string()),
+                )?),
+            ),
+            None => (None, None),
+        };
+        let millis =
+            |value: Option<i64>| value.map(|v| v.to_string()).ok_or_else(missing);
+        let (creation, lifetime) = (values.creation_timestamp, values.cache_lifetime);
+        let cache_expiry = cache_expiry(values).unwrap_or_else(|| Err(missing()));
+        Ok(Some(CustomerTerms {
+            npd_id: values.npd_id.clone(),
+            asnp_id: values.asnp_id.clone(),
+            response_type: values.response_type.clone(),
+            creation_date: layer(
+                "creationTimestamp",
+                millis(creation).and_then(|s| date_from_epoch_millis(&s)),
+            )?,
+            cache_lifetime: layer(
+                "cacheLifetime",
+                millis(lifetime).and_then(|s| duration_from_millis(&s)),
+            )?,
+            cache_expiry_date: layer(
+                "cacheLifetime",
+                cache_expiry
+                    .and_then(|millis| date_from_epoch_millis(&millis.to_string())),
+            )?,
+            cache_expiry_warning_start_date: warning_start_date,
+            cache_expiry_warning_interval: warning_interval,
+            challenge_codes: values.challenge_codes.clone(),
+            other_values: values.other_values.clone(),
+        }))
+    }
+
+    /// The ways in which the customer-signed values of this license
+    /// disagree with the rest of the license, one message per mismatch.
+    /// The customer-signed values echo the npdId of the license, and the
+    /// cache they describe expires (and starts warning) exactly when the
+    /// license does, so any difference means the license was altered.
+    pub fn customer_value_mismatches(&self) -> Vec<String> {
+        let mut result = Vec::new();
+        let values = match &self.customer_values {
+            Some(values) => values,
+            None => return result,
+        };
+        if values.npd_id != self.payload.npd_id {
+            result.push(format!(
+                "customer-signed npdId ({}) doesn't match the license npdId ({})",
+                &values.npd_id, &self.payload.npd_id
+            ));
+        }
+        let adobe = match self.payload.adobe_signed_values() {
+            Some(adobe) => &adobe.values,
+            None => return result,
+        };
+        let date = |millis: i64| {
+            date_from_epoch_millis(&millis.to_string())
+                .unwrap_or_else(|_| millis.to_string())
+        };
+        let adobe_expiry = adobe.license_expiry_timestamp.parse::<i64>();
+        if let (Some(Err(err)), Ok(_)) = (cache_expiry(values), &adobe_expiry) {
+            result.push(format!(
+                "customer-signed cache expiry doesn't match the license expiry ({})",
+                err
+            ));
+        }
+        if let (Some(Ok(cache_expiry)), Ok(expiry)) = (cache_expiry(values), adobe_expiry)
+        {
+            if cache_expiry != expiry {
+                result.push(format!(
+                    "customer-signed cache expiry ({}) doesn't match the license expiry ({})",
+                    date(cache_expiry),
+                    date(expiry)
+                ));
+            }
+        }
+        let warning = values.cache_expiry_warning_control.as_ref();
+        let adobe_warning = adobe.license_expiry_warning_start_timestamp.parse::<i64>();
+        if let (Some(control), Ok(start)) = (warning, adobe_warning) {
+            if control.warning_start_timestamp != start {
+                result.push(format!(
+                    "customer-signed expiry warning start ({}) doesn't match the license's ({})",
+                    date(control.warning_start_timestamp),
+                    date(start)
+                ));
+            }
+        }
+        result
+    }
+
+    /// The ways in which the name of this license's file disagrees with
+    /// the signed payload of the license, one message per mismatch.  The
+    /// file name gives the app ID, npdId, and precedence of the license,
+    /// and so does the payload, so any difference means the file was
+    /// renamed.  (The certificate group is only in the file name.)
+    pub fn filename_mismatches(&self) -> Vec<String> {
+        let mut result = Vec::new();
+        let payload = &self.payload;
+        if self.app_id != payload.ngl_app_id {
+            result.push(format!(
+                "file name app ID ({}) doesn't match the license app ID ({})",
+                &self.app_id, &payload.ngl_app_id
+            ));
+        }
+        if self.npd_id != payload.npd_id {
+            result.push(format!(
+                "file name npdId ({}) doesn't match the license npdId ({})",
+                &self.npd_id, &payload.npd_id
+            ));
+        }
+        if self.precedence as u32 != payload.npd_precedence {
+            result.push(format!(
+                "file name precedence ({}) doesn't match the license precedence ({})",
+                self.precedence as u32, payload.npd_precedence
+            ));
+        }
+        result
+    }
+
+    /// The spec versions that this license was decoded with: that of the
+    /// license file, and that of the preconditioning data it came from.
+    pub fn spec_versions(&self) -> Vec<SpecVersion> {
+        let mut result = vec![self.envelope.spec_version()];
+        if let Some(version) = &self.npd_spec_version {
+            result.push(SpecVersion::new(
+                "npdSpecVersion",
+                version,
+                NPD_SPEC_VERSIONS,
+            ));
+        }
+        result
+    }
+
+    /// Warnings about the spec versions of this license that the
+    /// decoder doesn't know, and so decoded with best effort.
+    pub fn spec_version_warnings(&self) -> Vec<String> {
+        let versions = self.spec_versions();
+        versions.iter().filter_map(SpecVersion::warning).collect()
+    }
+
+    /// The names of the device parameters that apps send to the license
+    /// server with this license.  Only licenses that get their profile
+    /// from a server send any.
+    pub fn device_params(&self) -> &[String] {
+        match &self.payload.profile_request_payload_params {
+            Some(params) => &params.device_params,
+            None => &[],
+        }
+    }
+
+    /// Look in the current user's credential store for a cached activation
+    /// of this license, and return its expiry timestamp (in epoch millis).
     pub fn get_cached_expiry(&self) -> Result<String> {
         let err = || eyre!("Malformed license");
         let app_name = self.app_id.as_str();
         // each type of licensing uses a different cert group for cached data
-        let cert_group_base = &self.cert_group_id[..self.cert_group_id.len() - 2];
+        let split = (self.cert_group_id.len().checked_sub(2))
+            .filter(|&i| self.cert_group_id.is_char_boundary(i))
+            .ok_or_else(err)?;
+        let (cert_group_base, cert_group_suffix) = self.cert_group_id.split_at(split);
         let cert_group_suffix = match self.mode {
             FrlOnline(_) => "03",
             FrlOffline => "06",
             FrlIsolated(_) => "06",
-            FrlLan(_) => "09",
+            FrlLan(..) => "09",
             Sdl => "13",
-            Unknown(_) => &self.cert_group_id[self.cert_group_id.len() - 2..],
+            Unknown(_) => cert_group_suffix,
         };
         let cert_name = format!("{}{}", cert_group_base, cert_group_suffix);
         let note_key = u64encode(&format!("{}{{}}{}", app_name, &cert_name))?;
         let note = get_saved_credential(&note_key)?;
         let json = json_from_str(&note)?;
         // npdId is in the customer ASNP
-        let asnp = json["custAsnp"].as_str().ok_or_else(err)?;
+        let asnp = json_field(&json, "custAsnp").as_str().ok_or_else(err)?;
         let inner_json = json_from_str(asnp)?;
-        let payload = inner_json["payload"].as_str().ok_or_else(err)?;
+        let payload = json_field(&inner_json, "payload")
+            .as_str()
+            .ok_or_else(err)?;
         let inner_json = json_from_base64(payload)?;
-        let npd_id = inner_json["npdId"].as_str().ok_or_else(err)?;
+        let npd_id = json_field(&inner_json, "npdId").as_str().ok_or_else(err)?;
         if !self.npd_id.eq_ignore_ascii_case(npd_id) {
             return Err(eyre!("Cached npdId does not match license npdId"));
         }
         // legacy profile is in the Adobe ASNP
-        let asnp = json["asnp"].as_str().ok_or_else(err)?;
+        let asnp = json_field(&json, "asnp").as_str().ok_or_else(err)?;
         let inner_json = json_from_str(asnp)?;
-        let payload = inner_json["payload"].as_str().ok_or_else(err)?;
+        let payload = json_field(&inner_json, "payload")
+            .as_str()
+            .ok_or_else(err)?;
         let inner_json = json_from_base64(payload)?;
-        let legacy_profile = inner_json["legacyProfile"].as_str().ok_or_else(err)?;
+        let legacy_profile = json_field(&inner_json, "legacyProfile")
+            .as_str()
+            .ok_or_else(err)?;
         let json = json_from_str(legacy_profile)?;
-        let timestamp = json["effectiveEndTimestamp"].as_i64().ok_or_else(err)?;
+        let timestamp = json_field(&json, "effectiveEndTimestamp")
+            .as_i64()
+            .ok_or_else(err)?;
         Ok(timestamp.to_string())
     }
 }
 
+/// When the customer-signed values stop being cached, in epoch millis,
+/// or `None` if the values don't say.  An expiry too far in the future
+/// to be represented is an error, since no real license has one.
+fn cache_expiry(values: &CustomerValues) -> Option<Result<i64>> {
+    let (creation, lifetime) = (values.creation_timestamp?, values.cache_lifetime?);
+    let expiry = creation.checked_add(lifetime).ok_or_else(|| {
+        eyre!(
+            "Cache lifetime ({}) is out of range for creation timestamp ({})",
+            lifetime,
+            creation
+        )
+    });
+    Some(expiry)
+}
+
+/// The error for a license value that is needed to describe
+/// the license in detail, but is missing from it.
+fn missing() -> eyre::Report {
+    eyre!("Missing value")
+}
+
+/// A license value that is empty because it is missing from the license.
+fn present(value: &str) -> Result<&str> {
+    if value.is_empty() {
+        Err(missing())
+    } else {
+        Ok(value)
+    }
+}
+
+/// The license terms that Adobe signed in a license.  The effective end
+/// date is when the license's term ends; the license expiry date (which
+/// includes the grace period) is when apps stop working.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LicenseTerms {
+    pub license_id: String,
+    pub profile_status: String,
+    pub app_entitlement_status: String,
+    pub effective_end_date: String,
+    /// When apps start warning users that the license will expire.
+    pub expiry_warning_start_date: String,
+    pub grace_period: String,
+    /// How often apps check with the licensing library.
+    pub app_refresh_interval: String,
+    /// How often the licensing library refreshes the license.
+    pub ngl_lib_refresh_interval: String,
+}
+
+/// What a license grants: the licensed product and its features.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LicenseFeatures {
+    pub enigma_data: EnigmaData,
+    pub licensed_features: Vec<String>,
+}
+
+/// The values that the customer signed in a license, which are the
+/// values that apps cache when the license is activated.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomerTerms {
+    pub npd_id: String,
+    pub asnp_id: String,
+    pub response_type: String,
+    pub creation_date: String,
+    pub cache_lifetime: String,
+    /// The creation date plus the cache lifetime.
+    pub cache_expiry_date: String,
+    pub cache_expiry_warning_start_date: Option<String>,
+    pub cache_expiry_warning_interval: Option<String>,
+    /// Census codes for isolated licenses; offline licenses have a
+    /// single, longer code.
+    pub challenge_codes: Vec<String>,
+    /// Any other values, such as device or override settings.
+    pub other_values: BTreeMap<String, serde_json::Value>,
+}
+
+/// The spec version declared by parsed preconditioning data.
+pub fn npd_spec_version(data: &JsonMap) -> SpecVersion {
+    let version = json_field(data, "npdSpecVersion").as_str();
+    SpecVersion::new(
+        "npdSpecVersion",
+        version.unwrap_or_default(),
+        NPD_SPEC_VERSIONS,
+    )
+}
+
+/// The license files in preconditioning data, read with the model for
+/// the data's spec version.
+fn package_license_files(data: &JsonMap, spec: &SpecVersion) -> Result<Vec<PackageFile>> {
+    let ocs = json_field(data, "operatingConfigs").clone();
+    let files = match spec.version.as_str() {
+        // 1.1 (used by SDL packages) only differs from 1.0 in leaving
+        // out the ASNP files, which are optional in both
+        "1.0" | "1.1" => serde_json::from_value(ocs),
+        // best effort for unknown versions
+        _ => serde_json::from_value(ocs),
+    };
+    files.wrap_err("Invalid preconditioning data: bad license data")
+}
+
+/// Extract the preconditioning data from the content of a package
+/// description (ccp) file.
+pub fn preconditioning_json_from_ccp(bytes: &[u8]) -> Result<JsonMap> {
+    // on Windows, this may be a zip file, and we need to extract
+    // the PkgConfig.xml file from it
+    let reader = std::io::Cursor::new(bytes);
+    let html = if let Ok(mut archive) = zip::ZipArchive::new(reader) {
+        let mut file = archive
+            .by_name("PkgConfig.xml")
+            .map_err(|e| eyre!(e))
+            .wrap_err("Can't find configuration data in ccp archive")?;
+        let mut buffer = String::new();
+        file.read_to_string(&mut buffer)
+            .wrap_err("Can't read configuration data from ccp archive")?;
+        buffer
+    } else {
+        from_utf8(bytes)
+            .wrap_err("Invalid ccp file format")?
+            .to_string()
+    };
+    // the HTML parser panics on some malformed documents, so those
+    // are reported as unparseable rather than taking down the decoder
+    let data = catch_quiet_unwind(|| {
+        let doc = visdom::Vis::load(&html).map_err(|e| eyre!("{}", e))?;
+        Ok(doc.find("Preconditioning").text().to_string())
+    })
+    .unwrap_or_else(|_| Err(eyre!("Malformed configuration data")))
+    .wrap_err("Cannot parse ccp file")?;
+    serde_json::from_str(&data).wrap_err("Can't parse preconditioning data in ccp file")
+}
+
+thread_local! {
+    static QUIET_PANICS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Run `f`, catching any panic in it without the panic hook reporting
+/// it.  Panics outside of `f`, and on other threads, are still reported.
+/// This relies on panics unwinding, which the crate's profiles require.
+fn catch_quiet_unwind<T>(f: impl FnOnce() -> T + UnwindSafe) -> std::thread::Result<T> {
+    static INSTALL_HOOK: Once = Once::new();
+    INSTALL_HOOK.call_once(|| {
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if !QUIET_PANICS.with(Cell::get) {
+                hook(info)
+            }
+        }))
+    });
+    QUIET_PANICS.with(|quiet| quiet.set(true));
+    let result = std::panic::catch_unwind(f);
+    QUIET_PANICS.with(|quiet| quiet.set(false));
+    result
+}
+
+/// The licenses decoded from the license files in a directory.
+#[derive(Default)]
+pub struct DirectoryLicenses {
+    /// The decoded licenses, sorted by npdId and then by app ID.
+    pub licenses: Vec<OperatingConfig>,
+    /// The files that could not be decoded.
+    pub errors: Vec<FileError>,
+}
+
+impl DirectoryLicenses {
+    /// Whether there were license files, but none of them could be decoded.
+    pub fn all_failed(&self) -> bool {
+        self.licenses.is_empty() && !self.errors.is_empty()
+    }
+}
+
+/// A license file that could not be decoded, and why.
+pub struct FileError {
+    pub pathname: String,
+    pub error: eyre::Report,
+}
+
 #[cfg(target_os = "macos")]
 fn get_saved_credential(key: &str) -> Result<String> {
     let service = format!("Adobe App Info ({})", &key);
@@ -249,15 +717,106 @@ fn get_saved_credential(key: &str) -> Result<String> {
     }
 }
 
+/// The type of license, with any type-specific data.  LAN licenses
+/// have the URL of their server and the fingerprint of the server
+/// certificate that apps pin, if there is one.
 pub enum DeploymentMode {
     FrlOnline(String),
     FrlOffline,
     FrlIsolated(Vec<String>),
-    FrlLan(String),
+    FrlLan(String, Option<String>),
     Sdl,
     Unknown(String),
 }
 
+impl DeploymentMode {
+    fn from_payload(
+        payload: &OcPayload, customer_values: Option<&CustomerValues>,
+    ) -> Result<DeploymentMode> {
+        let mode = match payload.deployment_mode.as_str() {
+            "NAMED_USER_EDUCATION_LAB" => Sdl,
+            "FRL_CONNECTED" => {
+                let server = payload
+                    .profile_server_url
+                    .as_deref()
+                    .unwrap_or("http://lcs-cops.adobe.io")
+                    .to_string();
+                FrlOnline(server)
+            }
+            "FRL_LAN" => {
+                let server = payload.profile_server_url.clone().ok_or_else(|| {
+                    decode_error(&["profileServerUrl"], "missing from an FRL LAN license")
+                })?;
+                FrlLan(server, payload.profile_server_cert_fingerprint.clone())
+            }
+            "FRL_ISOLATED" => {
+                let path = "asnpData.customerCertSignedValues";
+                let values = customer_values.ok_or_else(|| {
+                    decode_error(&[path], "missing from an FRL Isolated license")
+                })?;
+                let path = "asnpData.customerCertSignedValues.values";
+                let codes = &values.challenge_codes;
+                let code0 = codes.first().ok_or_else(|| {
+                    decode_error(&[path, "challengeCodes"], "missing or empty")
+                })?;
+                if code0.len() > 18 {
+                    FrlOffline
+                } else {
+                    let codes = codes
+                        .iter()
+                        .map(|code| {
+                            // codes are ASCII, so slicing by byte is safe
+                            if code.len() != 18 || !code.is_ascii() {
+                                "invalid-census-code".to_string()
+                            } else {
+                                format!(
+                                    "{}-{}-{}",
+                                    &code[0..6],
+                                    &code[6..12],
+                                    &code[12..18]
+                                )
+                            }
+                        })
+                        .collect();
+                    FrlIsolated(codes)
+                }
+            }
+            s => Unknown(s.to_string()),
+        };
+        Ok(mode)
+    }
+}
+
+impl Serialize for DeploymentMode {
+    fn serialize<S: Serializer>(
+        &self, serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        match self {
+            FrlOnline(server) => {
+                map.serialize_entry("type", "frlOnline")?;
+                map.serialize_entry("server", server)?;
+            }
+            FrlOffline => map.serialize_entry("type", "frlOffline")?,
+            FrlIsolated(codes) => {
+                map.serialize_entry("type", "frlIsolated")?;
+                map.serialize_entry("censusCodes", codes)?;
+            }
+            FrlLan(server, fingerprint) => {
+                map.serialize_entry("type", "frlLan")?;
+                map.serialize_entry("server", server)?;
+                map.serialize_entry("serverCertFingerprint", fingerprint)?;
+            }
+            Sdl => map.serialize_entry("type", "sdl")?,
+            Unknown(s) => {
+                map.serialize_entry("type", "unknown")?;
+                map.serialize_entry("name", s)?;
+            }
+        }
+        map.end()
+    }
+}
+
 impl std::fmt::Display for DeploymentMode {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -267,13 +826,16 @@ impl std::fmt::Display for DeploymentMode {
                 1 => "FRL Isolated (1 census code)".fmt(f),
                 n => format!("FRL Isolated ({} census codes)", n).fmt(f),
             },
-            FrlLan(server) => format!("FRL LAN (server: {})", server).fmt(f),
+            FrlLan(server, _) => format!("FRL LAN (server: {})", server).fmt(f),
             Sdl => "SDL".fmt(f),
             Unknown(s) => s.fmt(f),
         }
     }
 }
 
+/// The precedence of a license, which determines which of several
+/// licenses for the same app is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Precedence {
     AcrobatStandard = 70,
     AcrobatPro = 100,
@@ -281,6 +843,26 @@ pub enum Precedence {
     CcAllApps = 90,
 }
 
+impl PartialOrd for Precedence {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Precedence {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (*self as u32).cmp(&(*other as u32))
+    }
+}
+
+impl Serialize for Precedence {
+    fn serialize<S: Serializer>(
+        &self, serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_u32(*self as u32)
+    }
+}
+
 impl std::fmt::Display for Precedence {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -303,3 +885,349 @@ impl Precedence {
         }
     }
 }
+
+/// The Adobe licensing environment that a license was made in.  Stage
+/// licenses are for testing Adobe's licensing servers, and only work
+/// with apps that are configured to use those servers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Environment {
+    Production,
+    Stage,
+}
+
+impl Environment {
+    /// The environment named by the optional last part of a license
+    /// file name, which is missing for production licenses.
+    pub fn from(suffix: Option<&str>) -> Result<Environment> {
+        match suffix {
+            None => Ok(Production),
+            Some("stg") => Ok(Stage),
+            Some(s) => Err(eyre!("Environment ({}) must be stg if present", s)),
+        }
+    }
+
+    /// The environment of a license server, judged by its host name.
+    pub fn from_server_url(url: &str) -> Environment {
+        let host = url.split("://").last().unwrap_or_default();
+        let host = host.split(&['/', ':'][..]).next().unwrap_or_default();
+        let mut labels = host.split(&['.', '-'][..]);
+        if labels.any(|label| label.eq_ignore_ascii_case("stage")) {
+            Stage
+        } else {
+            Production
+        }
+    }
+}
+
+impl std::fmt::Display for Environment {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Production => "production".fmt(f),
+            Stage => "stage".fmt(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_directory() {
+        let info = FileInfo::from_path("rsrc/files").unwrap();
+        let ocs = OperatingConfig::from_directory(&info).unwrap().licenses;
+        assert_eq!(ocs.len(), 4);
+        assert_eq!(ocs[0].app_id, "Bridge1");
+        assert_eq!(ocs[1].app_id, "Illustrator1");
+        let fingerprint =
+            "0410EC06978DF3C1029B6D479704591A7AB0C5135C19CCC84158C24B9F0ACA64\
+            BE5164BF708988C3129CA78EC03B11D6E6279D3CDD066A6FCAFD19B3DEF42570";
+        assert!(matches!(&ocs[1].mode, FrlLan(_, Some(f)) if f == fingerprint));
+        assert_eq!(ocs[1].device_params().len(), 12);
+        assert_eq!(ocs[1].device_params()[2], "deviceName");
+        assert!(ocs[3].device_params().is_empty());
+        assert!(ocs[2].npd_id.starts_with("ZGQz"));
+        assert!(matches!(&ocs[3].mode, FrlIsolated(codes) if codes.len() == 2));
+    }
+
+    #[test]
+    fn test_from_directory_with_broken_file() {
+        let name = format!("adobe-license-decoder-broken-files-{}", std::process::id());
+        let dir = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        let good = "SWxsdXN0cmF0b3Ixe30yMDE4MDcyMDA0-OTUzZTViZWYtYWJmMy00NGUxLWFjYjUtZmZhN2MyMDY4YjQx-80.operatingconfig";
+        std::fs::copy(format!("rsrc/files/{}", good), dir.join(good)).unwrap();
+        std::fs::write(dir.join("broken.operatingconfig"), "not a license").unwrap();
+        let info = FileInfo::from_path(dir.to_str().unwrap()).unwrap();
+        let decoded = OperatingConfig::from_directory(&info).unwrap();
+        assert_eq!(decoded.licenses.len(), 1);
+        assert_eq!(decoded.errors.len(), 1);
+        assert!(decoded.errors[0]
+            .pathname
+            .ends_with("broken.operatingconfig"));
+        assert!(!decoded.all_failed());
+        // with only the broken file left, nothing could be decoded
+        std::fs::remove_file(dir.join(good)).unwrap();
+        let decoded = OperatingConfig::from_directory(&info).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(decoded.all_failed());
+    }
+
+    #[test]
+    fn test_license_terms() {
+        let info = FileInfo::from_path("rsrc/files").unwrap();
+        let ocs = OperatingConfig::from_directory(&info).unwrap().licenses;
+        // the LAN licenses get their terms from the server
+        assert!(ocs[0].license_terms().unwrap().is_none());
+        let mut oc = ocs.into_iter().last().unwrap();
+        let terms = oc.license_terms().unwrap().unwrap();
+        assert_eq!(terms.license_id, "96CB7B129BCA2124D48A");
+        assert_eq!(terms.app_entitlement_status, "SUBSCRIPTION");
+        assert_eq!(terms.grace_period, "30 days");
+        assert_eq!(
+            terms.app_refresh_interval,
+            "311 days 2 hours 9 minutes 48 seconds"
+        );
+        let features = oc.license_features().unwrap().unwrap();
+        assert_eq!(features.enigma_data.product_id, 45);
+        assert_eq!(features.licensed_features.len(), 19);
+        let adobe = oc.payload.asnp_data.as_mut().unwrap();
+        let adobe = adobe.adobe_cert_signed_values.as_mut().unwrap();
+        adobe.values.grace_time = "a month".into();
+        let err = oc.license_terms().unwrap_err();
+        assert!(format!("{:#}", err).starts_with(
+            "payload → asnpData.adobeCertSignedValues.values.graceTime: Illegal license duration"
+        ));
+    }
+
+    #[test]
+    fn test_optional_license_values() {
+        // a license without the values that are only needed for details
+        let name = "UGhvdG9zaG9wMXt9MjAxODA3MjAwNA-ZGQzMjhhY2MtZTE2Yy00NTI0LTgzOWItZGRkMDUwNTIzNGU0-80";
+        let path = format!("rsrc/files/{}.operatingconfig", name);
+        let mut envelope: serde_json::Value =
+            serde_json::from_str(&read_to_string(path).unwrap()).unwrap();
+        let payload = u64decode(envelope["payload"].as_str().unwrap()).unwrap();
+        let mut payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        let asnp = &mut payload["asnpData"];
+        let adobe = &mut asnp["adobeCertSignedValues"];
+        adobe.as_object_mut().unwrap().remove("signatures");
+        let values = adobe["values"].as_object_mut().unwrap();
+        values.remove("graceTime");
+        values.remove("licenseId");
+        let customer = &mut asnp["customerCertSignedValues"];
+        let values = u64decode(customer["values"].as_str().unwrap()).unwrap();
+        let mut values: serde_json::Value = serde_json::from_str(&values).unwrap();
+        values.as_object_mut().unwrap().remove("cacheLifetime");
+        customer["values"] = u64encode(&values.to_string()).unwrap().into();
+        asnp.as_object_mut().unwrap().remove("templateId");
+        payload.as_object_mut().unwrap().remove("id");
+        envelope["payload"] = u64encode(&payload.to_string()).unwrap().into();
+        let info = FileInfo::from_name_and_extension(name, "operatingconfig").unwrap();
+        let oc =
+            OperatingConfig::from_license_data(&info, &envelope.to_string()).unwrap();
+        assert_eq!(oc.app_id, "Photoshop1");
+        assert_eq!(oc.expiry_date, "2021-11-04");
+        assert!(oc.customer_value_mismatches().is_empty());
+        // the details that need the missing values can't be described
+        let err = oc.license_terms().unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "payload → asnpData.adobeCertSignedValues.values.graceTime: Missing value"
+        );
+        let err = oc.customer_terms().unwrap_err();
+        assert!(format!("{:#}", err).ends_with("values.cacheLifetime: Missing value"));
+    }
+
+    #[test]
+    fn test_filename_mismatches() {
+        let info = FileInfo::from_path("rsrc/files").unwrap();
+        let ocs = OperatingConfig::from_directory(&info).unwrap().licenses;
+        assert!(ocs.iter().all(|oc| oc.filename_mismatches().is_empty()));
+        // a file renamed to raise its precedence and change its package
+        let mut oc = ocs.into_iter().last().unwrap();
+        oc.precedence = CcAllApps;
+        oc.npd_id = "copied".to_string();
+        let mismatches = oc.filename_mismatches();
+        assert_eq!(mismatches.len(), 2);
+        assert!(mismatches[0].starts_with("file name npdId (copied)"));
+        assert_eq!(
+            mismatches[1],
+            "file name precedence (90) doesn't match the license precedence (80)"
+        );
+    }
+
+    #[test]
+    fn test_customer_terms() {
+        let info = FileInfo::from_path("rsrc/files").unwrap();
+        let ocs = OperatingConfig::from_directory(&info).unwrap().licenses;
+        // the LAN licenses aren't signed by the customer
+        assert!(ocs[0].customer_terms().unwrap().is_none());
+        assert!(ocs[0].customer_value_mismatches().is_empty());
+        let mut oc = ocs.into_iter().last().unwrap();
+        let terms = oc.customer_terms().unwrap().unwrap();
+        assert_eq!(terms.npd_id, oc.npd_id);
+        assert_eq!(terms.response_type, "FRL_INITIAL");
+        assert_eq!(terms.challenge_codes.len(), 2);
+        assert_eq!(terms.cache_expiry_date, oc.expiry_date);
+        assert_eq!(
+            terms.cache_expiry_warning_interval.as_deref(),
+            Some("1 day")
+        );
+        assert!(terms.other_values.is_empty());
+        assert!(oc.customer_value_mismatches().is_empty());
+        let values = oc.customer_values.as_mut().unwrap();
+        values.npd_id = "tampered".into();
+        values.cache_lifetime = values.cache_lifetime.map(|millis| millis + 86_400_000);
+        let mismatches = oc.customer_value_mismatches();
+        assert_eq!(mismatches.len(), 2);
+        assert!(mismatches[0].starts_with("customer-signed npdId (tampered)"));
+        assert!(mismatches[1].starts_with("customer-signed cache expiry"));
+        // a lifetime that overflows is reported rather than wrapping around
+        let values = oc.customer_values.as_mut().unwrap();
+        values.cache_lifetime = Some(i64::MAX);
+        let mismatches = oc.customer_value_mismatches();
+        assert_eq!(mismatches.len(), 2);
+        assert!(mismatches[1]
+            .contains("Cache lifetime (9223372036854775807) is out of range"));
+        let err = oc.customer_terms().unwrap_err();
+        assert!(format!("{:#}", err).contains("values.cacheLifetime: Cache lifetime"));
+    }
+
+    #[test]
+    fn test_malformed_data() {
+        let parse = |s: &str| -> JsonMap { serde_json::from_str(s).unwrap() };
+        for data in &[
+            r#"{}"#,
+            r#"{"operatingConfigs": 3}"#,
+            r#"{"operatingConfigs": [{}]}"#,
+            r#"{"operatingConfigs": [{"name": "x", "extension": "operatingconfig"}]}"#,
+        ] {
+            assert!(OperatingConfig::from_preconditioning_json(&parse(data)).is_err());
+        }
+        let name = "QnJpZGdlMXt9MjAxODA3MjAwNA-ZGQzMjhhY2MtZTE2Yy00NTI0LTgzOWItZGRkMDUwNTIzNGU0-80";
+        let info = FileInfo::from_name_and_extension(name, "operatingconfig").unwrap();
+        let mut oc = OperatingConfig::from_file_info(&info).unwrap();
+        let lan = r#"{"id": "", "npdId": "", "nglAppId": "", "npdPrecedence": 80,
+            "asnpData": {"templateId": ""}, "deploymentMode": "FRL_LAN"}"#;
+        for payload in &["e30", "not base64", &u64encode(lan).unwrap()] {
+            let envelope = OcEnvelope {
+                payload: payload.to_string(),
+                ..Default::default()
+            };
+            assert!(oc.update_from_license_data(envelope).is_err());
+        }
+        let envelope = OcEnvelope {
+            payload: u64encode(lan).unwrap(),
+            ..Default::default()
+        };
+        let err = oc.update_from_license_data(envelope).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "payload → profileServerUrl: missing from an FRL LAN license"
+        );
+        oc.cert_group_id = "Ã".to_string();
+        assert!(oc.get_cached_expiry().is_err());
+        assert!(OperatingConfig::from_ccp_data(b"<Preconditioning>{}").is_err());
+        let malformed = b"<PackageInfo><Preconditioning>{}</Preconditioning><a b=\"";
+        assert!(OperatingConfig::from_ccp_data(malformed).is_err());
+        // a fuzzer input that makes the HTML parser panic
+        let err = OperatingConfig::from_ccp_data(b"<Preconditioning><Z")
+            .err()
+            .unwrap();
+        assert_eq!(
+            format!("{:#}", err),
+            "Cannot parse ccp file: Malformed configuration data"
+        );
+    }
+
+    #[test]
+    fn test_from_license_data() {
+        let name = "UGhvdG9zaG9wMXt9MjAxODA3MjAwNA-ZGQzMjhhY2MtZTE2Yy00NTI0LTgzOWItZGRkMDUwNTIzNGU0-80";
+        let path = format!("rsrc/files/{}.operatingconfig", name);
+        let data = std::fs::read_to_string(path).unwrap();
+        let info = FileInfo::from_name_and_extension(name, "operatingconfig").unwrap();
+        let oc = OperatingConfig::from_license_data(&info, &data).unwrap();
+        assert_eq!(oc.app_id, "Photoshop1");
+        assert!(matches!(&oc.mode, FrlIsolated(codes) if codes.len() == 2));
+        assert!(OperatingConfig::from_license_data(&info, "{}").is_err());
+    }
+
+    #[test]
+    fn test_spec_versions() {
+        let path = "rsrc/packages/mac/isolated-photoshop/ngl-preconditioning-data.json";
+        let mut data = json_from_file(&FileInfo::from_path(path).unwrap()).unwrap();
+        let ocs = OperatingConfig::from_preconditioning_json(&data).unwrap();
+        assert_eq!(ocs[0].oc_spec_version, "1.1");
+        assert_eq!(ocs[0].npd_spec_version.as_deref(), Some("1.0"));
+        assert!(ocs[0].spec_version_warnings().is_empty());
+        // a future version is decoded with best effort
+        data.insert("npdSpecVersion".into(), "2.0".into());
+        let ocs = OperatingConfig::from_preconditioning_json(&data).unwrap();
+        let warnings = ocs[0].spec_version_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("npdSpecVersion 2.0 is not a version"));
+        // and failures to decode it are explained
+        data.insert("operatingConfigs".into(), "none".into());
+        let err = OperatingConfig::from_preconditioning_json(&data)
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", err).starts_with("Can't decode: npdSpecVersion 2.0"));
+    }
+
+    #[test]
+    fn test_license_spec_versions() {
+        let name = "UGhvdG9zaG9wMXt9MjAxODA3MjAwNA-ZGQzMjhhY2MtZTE2Yy00NTI0LTgzOWItZGRkMDUwNTIzNGU0-80";
+        let path = format!("rsrc/files/{}.operatingconfig", name);
+        let mut envelope: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(&read_to_string(path).unwrap()).unwrap();
+        let info = FileInfo::from_name_and_extension(name, "operatingconfig").unwrap();
+        let decode = |envelope: &serde_json::Map<String, serde_json::Value>| {
+            let data = serde_json::to_string(envelope).unwrap();
+            OperatingConfig::from_license_data(&info, &data)
+        };
+        // a license without a version is decoded with best effort
+        envelope.remove("ocSpecVersion");
+        let oc = decode(&envelope).unwrap();
+        let warnings = oc.spec_version_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("ocSpecVersion is missing"));
+        // a future version explains why the envelope can't be decoded
+        envelope.insert("ocSpecVersion".into(), "2.0".into());
+        envelope.insert("payload".into(), serde_json::json!({"moved": true}));
+        let err = decode(&envelope).err().unwrap();
+        assert!(format!("{:#}", err).starts_with("Can't decode: ocSpecVersion 2.0"));
+        // but a known version doesn't
+        envelope.insert("ocSpecVersion".into(), "1.1".into());
+        let err = decode(&envelope).err().unwrap();
+        assert!(format!("{:#}", err).starts_with("payload: invalid type"));
+    }
+
+    #[test]
+    fn test_from_preconditioning_file() {
+        let path = "rsrc/packages/mac/offline-acrobat/ngl-preconditioning-data.json";
+        let info = FileInfo::from_path(path).unwrap();
+        let ocs = OperatingConfig::from_preconditioning_file(&info).unwrap();
+        assert_eq!(ocs.len(), 1);
+        assert_eq!(ocs[0].app_id, "AcrobatDC1");
+        assert!(matches!(ocs[0].mode, FrlOffline));
+        assert!(matches!(ocs[0].precedence, AcrobatStandard));
+        assert_eq!(ocs[0].environment, Stage);
+        assert_eq!(ocs[0].expiry_date, "2021-07-12");
+        let stage = Environment::from_server_url("https://lcs-cops-stage.adobe.io/");
+        assert_eq!(stage, Stage);
+        let lan = Environment::from_server_url("https://backstage.example.com:8443");
+        assert_eq!(lan, Production);
+        assert!(Environment::from(Some("dev")).is_err());
+    }
+
+    #[test]
+    fn test_from_ccp_file() {
+        let path = "rsrc/packages/win/online-illustrator/online-illustrator.ccp";
+        let info = FileInfo::from_path(path).unwrap();
+        let ocs = OperatingConfig::from_ccp_file(&info).unwrap();
+        assert_eq!(ocs.len(), 2);
+        assert!(matches!(&ocs[0].mode, FrlOnline(server) if server.contains("lcs-cops")));
+    }
+}
@@ -10,15 +10,20 @@ extern crate base64;
 extern crate chrono;
 extern crate shellexpand;
 
+use crate::types::FileError;
 use chrono::prelude::*;
-use eyre::{Result, WrapErr};
+use eyre::{eyre, Result, WrapErr};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use serde_json::Value;
-use std::collections::HashMap;
-use std::fs::{metadata, File};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{metadata, read_to_string};
 use std::path::Path;
 
 pub type JsonMap = HashMap<String, Value>;
 
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct FileInfo {
     pub pathname: String,
     pub filename: String,
@@ -26,6 +31,8 @@ pub struct FileInfo {
     pub extension: String,
     pub is_directory: bool,
     pub mod_date: String,
+    #[serde(skip)]
+    pub mod_time: Option<DateTime<Local>>,
 }
 
 impl FileInfo {
@@ -54,6 +61,7 @@ impl FileInfo {
                 .to_string(),
             is_directory,
             mod_date: mod_date.format("%Y-%m-%d %H:%M:%S %Z").to_string(),
+            mod_time: Some(mod_date),
         })
     }
 
@@ -66,6 +74,7 @@ impl FileInfo {
             extension: extension.into(),
             is_directory: false,
             mod_date: "Unknown".into(),
+            mod_time: None,
         })
     }
 }
@@ -79,38 +88,185 @@ pub fn u64encode(s: &str) -> Result<String> {
     Ok(base64::encode_config(s, base64::URL_SAFE_NO_PAD))
 }
 
-pub fn json_from_base64(s: &str) -> Result<JsonMap> {
-    serde_json::from_str(&u64decode(s)?).wrap_err("Illegal payload data")
+/// An error found while decoding license data, which is JSON with
+/// base64-encoded JSON nested inside it.  The error says where it was
+/// found: the first layer is a path in the outermost JSON, and each
+/// following layer is a path in the JSON decoded from the layer before.
+#[derive(Debug)]
+pub struct DecodeError {
+    pub layers: Vec<String>,
+    pub message: String,
 }
 
-pub fn json_from_str(s: &str) -> Result<JsonMap> {
-    serde_json::from_str(s).wrap_err("Illegal license data")
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.layers.is_empty() {
+            self.message.fmt(f)
+        } else {
+            write!(f, "{}: {}", self.layers.join(" → "), self.message)
+        }
+    }
 }
 
-pub fn date_from_epoch_millis(timestamp: &str) -> Result<String> {
+impl std::error::Error for DecodeError {}
+
+/// Make a decode error found in the given layers.
+pub fn decode_error(layers: &[&str], message: &str) -> eyre::Report {
+    eyre::Report::new(DecodeError {
+        layers: layers.iter().map(|layer| layer.to_string()).collect(),
+        message: message.to_string(),
+    })
+}
+
+/// Add an outer layer to the location of a decode error.  Other errors
+/// become decode errors found in the given layer.
+pub fn in_layer<T>(layer: &str, result: Result<T>) -> Result<T> {
+    result.map_err(|err| {
+        let err = match err.downcast::<DecodeError>() {
+            Ok(err) => err,
+            Err(err) => DecodeError {
+                layers: Vec::new(),
+                message: format!("{:#}", err),
+            },
+        };
+        let mut layers = vec![layer.to_string()];
+        layers.extend(err.layers);
+        eyre::Report::new(DecodeError {
+            layers,
+            message: err.message,
+        })
+    })
+}
+
+pub fn json_from_base64<T: DeserializeOwned>(s: &str) -> Result<T> {
+    json_from_str(&u64decode(s).wrap_err("Illegal payload encoding")?)
+}
+
+/// The value of a field in a JSON map, or `null` if the field is missing.
+pub fn json_field<'a>(map: &'a JsonMap, key: &str) -> &'a Value {
+    map.get(key).unwrap_or(&Value::Null)
+}
+
+/// Parse JSON, reporting parse errors as decode errors found at the
+/// path of the failing value.
+pub fn json_from_str<T: DeserializeOwned>(s: &str) -> Result<T> {
+    let mut de = serde_json::Deserializer::from_str(s);
+    let value = serde_path_to_error::deserialize(&mut de).map_err(|err| {
+        let path = err.path().to_string();
+        let message = err.into_inner().to_string();
+        if path == "." {
+            decode_error(&[], &message)
+        } else {
+            decode_error(&[&path], &message)
+        }
+    })?;
+    de.end().wrap_err("Illegal license data")?;
+    Ok(value)
+}
+
+pub fn datetime_from_epoch_millis(timestamp: &str) -> Result<DateTime<Local>> {
     let timestamp = timestamp
         .parse::<i64>()
         .wrap_err("Illegal license timestamp")?;
-    let date = Local.timestamp(timestamp / 1000, 0);
+    Local
+        .timestamp_opt(timestamp / 1000, 0)
+        .single()
+        .ok_or_else(|| eyre!("License timestamp out of range: {}", timestamp))
+}
+
+pub fn date_from_epoch_millis(timestamp: &str) -> Result<String> {
+    let date = datetime_from_epoch_millis(timestamp)?;
     Ok(date.format("%Y-%m-%d").to_string())
 }
 
-pub fn json_from_file(info: &FileInfo) -> Result<JsonMap> {
-    let file =
-        File::open(Path::new(&info.pathname)).wrap_err("Can't read license file")?;
-    serde_json::from_reader(&file).wrap_err("Can't parse license data")
+/// Describe a duration given in millis, in whole days, hours, minutes,
+/// and seconds (for example, "1 day 12 hours").  Units that the duration
+/// has none of are left out, as are any leftover millis.
+pub fn duration_from_millis(millis: &str) -> Result<String> {
+    let millis = millis.parse::<i64>().wrap_err("Illegal license duration")?;
+    if millis < 0 {
+        return Err(eyre!("Illegal license duration: {}", millis));
+    }
+    let mut seconds = millis / 1000;
+    let mut parts = Vec::new();
+    for &(size, unit) in &[
+        (86_400, "day"),
+        (3_600, "hour"),
+        (60, "minute"),
+        (1, "second"),
+    ] {
+        let count = seconds / size;
+        seconds %= size;
+        if count > 0 {
+            parts.push(format!(
+                "{} {}{}",
+                count,
+                unit,
+                if count == 1 { "" } else { "s" }
+            ));
+        }
+    }
+    if parts.is_empty() {
+        parts.push("0 seconds".to_string());
+    }
+    Ok(parts.join(" "))
+}
+
+pub fn json_from_file<T: DeserializeOwned>(info: &FileInfo) -> Result<T> {
+    let data = read_to_string(&info.pathname).wrap_err("Can't read license file")?;
+    json_from_str(&data)
+}
+
+/// The directories in the tree under `root` (including `root` itself)
+/// that hold license files, preconditioning data, or ccp files, sorted
+/// by path, along with the directories in the tree that couldn't be read.
+pub fn license_directories(root: &str) -> Result<(Vec<String>, Vec<FileError>)> {
+    let mut result = BTreeSet::new();
+    let mut errors = BTreeMap::new();
+    for name in &[
+        "ngl-preconditioning-data.json",
+        "*.ccp",
+        "*.operatingconfig",
+    ] {
+        let pattern = format!("{}/**/{}", glob::Pattern::escape(root), name);
+        for path in glob::glob(&pattern)? {
+            match path {
+                Ok(path) => {
+                    if let Some(directory) = path.parent() {
+                        result.insert(directory.to_string_lossy().to_string());
+                    }
+                }
+                // each pattern walks the whole tree, so each
+                // unreadable directory is only reported once
+                Err(err) => {
+                    let pathname = err.path().to_string_lossy().to_string();
+                    let error = eyre!("{}", err.error());
+                    errors.entry(pathname).or_insert(error);
+                }
+            }
+        }
+    }
+    let errors = errors
+        .into_iter()
+        .map(|(pathname, error)| FileError {
+            pathname,
+            error: error.wrap_err("Can't read directory"),
+        })
+        .collect();
+    Ok((result.into_iter().collect(), errors))
 }
 
 pub fn shorten_oc_file_name(name: &str) -> Result<String> {
     let parts: Vec<&str> = name.split('-').collect();
-    if parts.len() != 3 {
-        Ok(name.to_string())
-    } else {
-        Ok(format!("{}-...-{}", parts[0], parts[2]))
+    match parts.len() {
+        // stage licenses have an extra part after the precedence
+        3 | 4 => Ok(format!("{}-...-{}", parts[0], parts[2..].join("-"))),
+        _ => Ok(name.to_string()),
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::redundant_pattern_matching)]
 mod tests {
     use super::*;
 
@@ -134,10 +290,69 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_decode_error_layers() {
+        let inner = u64encode(r#"{"codes": [1, "x"]}"#).unwrap();
+        let outer = format!(r#"{{"values": "{}"}}"#, inner);
+        let outer: JsonMap = json_from_str(&outer).unwrap();
+        let values = json_field(&outer, "values").as_str().unwrap();
+        let result: Result<HashMap<String, Vec<u32>>> = json_from_base64(values);
+        let err = in_layer("content", in_layer("values", result)).unwrap_err();
+        let err = err.downcast::<DecodeError>().unwrap();
+        assert_eq!(err.layers, vec!["content", "values", "codes[1]"]);
+        assert!(err.message.starts_with("invalid type: string \"x\""));
+        let err = in_layer::<()>("content", Err(eyre!("bad"))).unwrap_err();
+        assert_eq!(err.to_string(), "content: bad");
+        let err = json_from_str::<JsonMap>("{} x").unwrap_err();
+        assert!(err.downcast_ref::<DecodeError>().is_none());
+    }
+
+    #[test]
+    fn test_duration_from_millis() {
+        assert_eq!(duration_from_millis("2592000000").unwrap(), "30 days");
+        assert_eq!(
+            duration_from_millis("26878188870").unwrap(),
+            "311 days 2 hours 9 minutes 48 seconds"
+        );
+        assert_eq!(duration_from_millis("129600000").unwrap(), "1 day 12 hours");
+        assert_eq!(duration_from_millis("3600000").unwrap(), "1 hour");
+        assert_eq!(duration_from_millis("61999").unwrap(), "1 minute 1 second");
+        assert_eq!(duration_from_millis("0").unwrap(), "0 seconds");
+        assert!(duration_from_millis("soon").is_err());
+        assert!(duration_from_millis("-1000").is_err());
+    }
+
     #[test]
     fn test_file_info_from_name_and_extension() {
         let fi = FileInfo::from_name_and_extension("foo", "bar").unwrap();
         assert_eq!(fi.filename, "foo.bar");
         assert_eq!(fi.pathname, "foo.bar");
     }
+
+    #[test]
+    fn test_license_directories() {
+        let (directories, errors) = license_directories("rsrc/packages").unwrap();
+        assert_eq!(directories.len(), 9);
+        assert_eq!(directories[0], "rsrc/packages/mac/isolated-illustrator");
+        assert_eq!(directories[8], "rsrc/packages/win/online-illustrator");
+        assert!(errors.is_empty());
+        let (directories, _) = license_directories("rsrc/files").unwrap();
+        assert_eq!(directories, vec!["rsrc/files"]);
+        assert!(license_directories("rsrc/probe").unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn test_shorten_oc_file_name() {
+        let name = "QWNyb2JhdERDMXt9MjAxODA3MjAwNA-ODFhOWYy-70.operatingconfig";
+        assert_eq!(
+            shorten_oc_file_name(name).unwrap(),
+            "QWNyb2JhdERDMXt9MjAxODA3MjAwNA-...-70.operatingconfig"
+        );
+        let name = "QWNyb2JhdERDMXt9MjAxODA3MjAwNA-ODFhOWYy-70-stg.operatingconfig";
+        assert_eq!(
+            shorten_oc_file_name(name).unwrap(),
+            "QWNyb2JhdERDMXt9MjAxODA3MjAwNA-...-70-stg.operatingconfig"
+        );
+        assert_eq!(shorten_oc_file_name("a-b").unwrap(), "a-b");
+    }
 }
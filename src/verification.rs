@@ -0,0 +1,261 @@
+/*
+Copyright 2020 Adobe
+All Rights Reserved.
+
+NOTICE: Adobe permits you to use, modify, and distribute this file in
+accordance with the terms of the Adobe license agreement accompanying
+it.
+*/
+//! Offline verification of the signatures in license files.
+//!
+//! Each signature header names the certificate chain used to make the
+//! signature: a content certificate, issued by an intermediate certificate,
+//! which is in turn issued by a root certificate.  Preconditioning data
+//! ships the content and intermediate certificates, but never the roots,
+//! so the roots to trust have to be supplied separately.
+use crate::certificates::{common_name, hex, CertificateStore};
+use crate::models::SignedHeader;
+use crate::types::OperatingConfig;
+use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY};
+use ring::signature::{UnparsedPublicKey, RSA_PKCS1_2048_8192_SHA512};
+use serde::Serialize;
+
+/// The outcome of verifying the signatures in a license, from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SignatureStatus {
+    /// Every signature is correct and made with a trusted certificate chain.
+    Valid,
+    /// Every signature that could be checked is correct, but some
+    /// certificate chain is incomplete or doesn't end at a trusted root.
+    Untrusted,
+    /// Some signature is missing, malformed, or doesn't match what it signs.
+    Invalid,
+}
+
+impl std::fmt::Display for SignatureStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SignatureStatus::Valid => "valid".fmt(f),
+            SignatureStatus::Untrusted => "untrusted".fmt(f),
+            SignatureStatus::Invalid => "invalid".fmt(f),
+        }
+    }
+}
+
+/// The result of verifying the signatures in a license.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Verification {
+    pub status: SignatureStatus,
+    /// Why the license isn't valid, one entry per problem.
+    pub problems: Vec<String>,
+}
+
+type Problem = (SignatureStatus, String);
+
+fn invalid(message: String) -> Problem {
+    (SignatureStatus::Invalid, message)
+}
+
+fn untrusted(message: String) -> Problem {
+    (SignatureStatus::Untrusted, message)
+}
+
+/// Verify the signatures in a license.  The license signatures cover the
+/// payload of the license, and the customer signatures cover its
+/// customer-signed values.  The certificates named in the signature
+/// headers are looked up in `certificates`, and the chain they form
+/// must end at a certificate in `roots` for the license to be valid.
+pub fn verify_license(
+    oc: &OperatingConfig, certificates: &CertificateStore, roots: &CertificateStore,
+) -> Verification {
+    let mut problems: Vec<Problem> = Vec::new();
+    let envelope = &oc.envelope;
+    if envelope.signatures.is_empty() {
+        problems.push(invalid("the license is not signed".to_string()));
+    }
+    for (i, signed) in envelope.signatures.iter().enumerate() {
+        let message = format!("{}.{}", signed.header, envelope.payload);
+        let result =
+            verify_signature(signed, &signed.signature, &message, certificates, roots);
+        if let Err((status, problem)) = result {
+            problems.push((status, format!("license signature {}: {}", i + 1, problem)));
+        }
+    }
+    if let (Some(asnp), Some(values)) =
+        (&oc.payload.asnp_data, oc.payload.customer_signed_values())
+    {
+        for (i, signed) in asnp.customer_cert_headers.iter().enumerate() {
+            // each header names the signature it describes
+            let signature = match signed.signature.as_str() {
+                "{{customerSignature1}}" => &values.signatures.customer_signature1,
+                "{{customerSignature2}}" => &values.signatures.customer_signature2,
+                other => other,
+            };
+            let message = format!("{}.{}", signed.header, values.values);
+            let result =
+                verify_signature(signed, signature, &message, certificates, roots);
+            if let Err((status, problem)) = result {
+                problems
+                    .push((status, format!("customer signature {}: {}", i + 1, problem)));
+            }
+        }
+    }
+    Verification {
+        status: problems
+            .iter()
+            .map(|(status, _)| *status)
+            .max()
+            .unwrap_or(SignatureStatus::Valid),
+        problems: problems.into_iter().map(|(_, problem)| problem).collect(),
+    }
+}
+
+fn verify_signature(
+    signed: &SignedHeader, signature: &str, message: &str,
+    certificates: &CertificateStore, roots: &CertificateStore,
+) -> std::result::Result<(), Problem> {
+    let header = signed
+        .decode_header()
+        .map_err(|e| invalid(format!("malformed header: {:#}", e)))?;
+    if header.content_signature_alg != "RS512" {
+        return Err(invalid(format!(
+            "unsupported signature algorithm {}",
+            header.content_signature_alg
+        )));
+    }
+    let signature = signature.trim_end_matches('=');
+    let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD)
+        .map_err(|_| invalid("the signature is malformed".to_string()))?;
+    let details = header.chain();
+    if details.is_empty() {
+        return Err(invalid("the header names no certificates".to_string()));
+    }
+    let mut chain = Vec::new();
+    for detail in details {
+        let name = &detail.subject_name;
+        let der = certificates.get(&detail.download_path).ok_or_else(|| {
+            untrusted(format!("certificate '{}' is not available", name))
+        })?;
+        let fingerprint = digest(&SHA1_FOR_LEGACY_USE_ONLY, der);
+        if !hex(fingerprint.as_ref()).eq_ignore_ascii_case(&detail.sha1_hash) {
+            return Err(invalid(format!(
+                "certificate '{}' doesn't match its fingerprint",
+                name
+            )));
+        }
+        let (_, cert) = x509_parser::parse_x509_certificate(der)
+            .map_err(|_| invalid(format!("certificate '{}' is malformed", name)))?;
+        chain.push(cert);
+    }
+    let key = UnparsedPublicKey::new(
+        &RSA_PKCS1_2048_8192_SHA512,
+        chain[0].public_key().subject_public_key.as_ref(),
+    );
+    key.verify(message.as_bytes(), &signature).map_err(|_| {
+        invalid("the signature doesn't match the signed data".to_string())
+    })?;
+    for pair in chain.windows(2) {
+        let (cert, issuer) = (&pair[0], &pair[1]);
+        if cert.issuer() != issuer.subject()
+            || cert.verify_signature(Some(issuer.public_key())).is_err()
+        {
+            return Err(invalid(format!(
+                "certificate '{}' was not issued by '{}'",
+                common_name(cert.subject()),
+                common_name(issuer.subject())
+            )));
+        }
+    }
+    let last = &chain[chain.len() - 1];
+    if !roots.anchors(last) {
+        return Err(untrusted(format!(
+            "issuer '{}' is not a trusted root",
+            common_name(last.issuer())
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utilities::FileInfo;
+
+    // The licenses in rsrc/verify are signed with a test certificate chain,
+    // whose root is in rsrc/verify/roots.
+    const LICENSES: &str = "rsrc/verify/licenses";
+    const ROOTS: &str = "rsrc/verify/roots";
+
+    #[test]
+    fn test_verify_license() {
+        let info = FileInfo::from_path(LICENSES).unwrap();
+        let mut ocs = OperatingConfig::from_directory(&info).unwrap().licenses;
+        let certificates = CertificateStore::from_directory(LICENSES).unwrap();
+        assert_eq!(certificates.len(), 2);
+        let roots = CertificateStore::from_directory(ROOTS).unwrap();
+        let verification = verify_license(&ocs[0], &certificates, &roots);
+        assert_eq!(verification.status, SignatureStatus::Valid);
+        assert!(verification.problems.is_empty());
+        // without the root, the chain can't be trusted
+        let none = CertificateStore::default();
+        let verification = verify_license(&ocs[0], &certificates, &none);
+        assert_eq!(verification.status, SignatureStatus::Untrusted);
+        assert_eq!(verification.problems.len(), 3);
+        assert_eq!(
+            verification.problems[0],
+            "license signature 1: issuer 'Test Root CA 1' is not a trusted root"
+        );
+        // without the other certificates, the signatures can't be checked
+        let verification = verify_license(&ocs[0], &none, &roots);
+        assert_eq!(verification.status, SignatureStatus::Untrusted);
+        // a tampered payload doesn't match the license signature
+        ocs[0].envelope.payload.push('A');
+        let verification = verify_license(&ocs[0], &certificates, &roots);
+        assert_eq!(verification.status, SignatureStatus::Invalid);
+        assert_eq!(
+            verification.problems,
+            vec!["license signature 1: the signature doesn't match the signed data"]
+        );
+    }
+
+    #[test]
+    fn test_verify_package_license() {
+        // the certificates are shipped, but the license signatures are not
+        let path = "rsrc/packages/mac/isolated-photoshop/ngl-preconditioning-data.json";
+        let info = FileInfo::from_path(path).unwrap();
+        let data = crate::utilities::json_from_file(&info).unwrap();
+        let certificates = CertificateStore::from_preconditioning_json(&data).unwrap();
+        assert!(!certificates.is_empty());
+        let ocs = OperatingConfig::from_preconditioning_json(&data).unwrap();
+        let none = CertificateStore::default();
+        let verification = verify_license(&ocs[0], &certificates, &none);
+        assert_eq!(verification.status, SignatureStatus::Invalid);
+        assert_eq!(verification.problems.len(), 4);
+        assert_eq!(
+            verification.problems[1],
+            "license signature 2: the signature is malformed"
+        );
+        // the customer signatures are intact, but Adobe's root isn't shipped
+        assert!(verification.problems[2..]
+            .iter()
+            .all(|p| p.ends_with("is not a trusted root")));
+        // trusting the top of the shipped chain shows that the customer
+        // signatures and their certificate chain check out, and that only
+        // the root is missing
+        let asnp = ocs[0].payload.asnp_data.as_ref().unwrap();
+        let mut roots = CertificateStore::default();
+        for signed in &asnp.customer_cert_headers {
+            let header = signed.decode_header().unwrap();
+            let top = &header.chain().last().unwrap().download_path;
+            roots.add(top, certificates.get(top).unwrap().to_vec());
+        }
+        let verification = verify_license(&ocs[0], &certificates, &roots);
+        assert_eq!(verification.problems.len(), 2);
+        assert!(verification
+            .problems
+            .iter()
+            .all(|p| p.starts_with("license signature")));
+    }
+}